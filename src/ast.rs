@@ -1,43 +1,159 @@
 use std::collections::HashMap;
-use pyo3::prelude::*;
-use pyo3::{IntoPy, PyObject, Python, ToPyObject};
-use pyo3::exceptions::PyKeyError;
+use std::fmt;
 
 pub type T3dStruct = HashMap<String, T3dValue>;
 
-#[pyclass]
-#[derive(Debug, Clone)]
+/// Serializes a map with its keys in sorted order, so that the output is deterministic.
+#[cfg(feature = "serde")]
+fn serialize_sorted<S, M, V>(map: &M, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+    M: std::borrow::Borrow<HashMap<String, V>>,
+    V: serde::Serialize,
+{
+    use serde::Serialize;
+    let sorted: std::collections::BTreeMap<&String, &V> = map.borrow().iter().collect();
+    sorted.serialize(serializer)
+}
+
+/// A region of the source text, with 1-based line and column numbers.
+#[cfg_attr(feature = "python", pyo3::pyclass)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct T3dSpan {
+    /// Byte offset of the start of the region.
+    pub start: usize,
+    /// Byte offset of the end of the region (exclusive).
+    pub end: usize,
+    pub start_line: usize,
+    pub start_column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+impl fmt::Display for T3dSpan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.start_line, self.start_column)
+    }
+}
+
+#[cfg_attr(feature = "python", pyo3::pyclass)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct T3dReference {
-    #[pyo3(get)]
+    #[cfg_attr(feature = "serde", serde(rename = "type"))]
     pub type_: String,
-    #[pyo3(get)]
     pub path: String,
+    /// Whether the path is enclosed in double quotes, as UE4 does for asset paths (`StaticMesh'"/Game/Foo.Foo"'`).
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "std::ops::Not::not"))]
+    pub quoted: bool,
 }
 
-impl ToString for T3dReference {
-    fn to_string(&self) -> String {
-        format!("{}'{}'", self.type_, self.path)
+impl T3dReference {
+    pub fn new(type_: &str, path: &str) -> Self {
+        T3dReference { type_: String::from(type_), path: String::from(path), quoted: false }
+    }
+
+    /// Returns the class name without its package, e.g. `Actor` for `Class'/Script/Engine.Actor'` or
+    /// `StaticMeshActor` for `/Script/Engine.StaticMeshActor'...'`.
+    pub fn class_name(&self) -> &str {
+        self.type_.rsplit('.').next().unwrap_or_default()
+    }
+
+    /// Returns the path of the object that the reference is in, without any subobjects.
+    pub fn object_path(&self) -> &str {
+        self.path.split(':').next().unwrap_or_default()
+    }
+
+    /// Returns the package of the object, e.g. `Pkg` for `Pkg.Group.Name` or `/Game/Meshes/SM_Wall` for
+    /// `/Game/Meshes/SM_Wall.SM_Wall`. Paths that are relative to the current level, such as `StaticMeshComponent_0`,
+    /// have no package.
+    pub fn package(&self) -> Option<&str> {
+        self.object_path().split_once('.').map(|(package, _)| package)
+    }
+
+    /// Returns the groups between the package and the object name, e.g. `["Group"]` for `Pkg.Group.Name`.
+    pub fn groups(&self) -> Vec<&str> {
+        let parts: Vec<&str> = self.object_path().split('.').collect();
+        match parts.len() {
+            0..=2 => Vec::new(),
+            len => parts[1..len - 1].to_vec(),
+        }
+    }
+
+    /// Returns the name of the object, e.g. `Name` for `Pkg.Group.Name`.
+    pub fn object_name(&self) -> &str {
+        self.object_path().rsplit('.').next().unwrap_or_default()
+    }
+
+    /// Returns the chain of subobjects following the object path, e.g. `["StaticMeshComponent0"]` for
+    /// `Engine.Default__StaticMeshActor:StaticMeshComponent0`.
+    pub fn subobjects(&self) -> Vec<&str> {
+        match self.path.split_once(':') {
+            Some((_, subobjects)) => subobjects.split([':', '.']).collect(),
+            None => Vec::new(),
+        }
     }
 }
 
-#[pymethods]
-impl T3dReference {
-    fn __repr__(&self) -> String {
-        self.to_string()
+impl fmt::Display for T3dReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.quoted {
+            write!(f, "{}'\"{}\"'", self.type_, self.path)
+        } else {
+            write!(f, "{}'{}'", self.type_, self.path)
+        }
     }
+}
+
+/// A problem that was skipped over while parsing in recovery mode.
+#[cfg_attr(feature = "python", pyo3::pyclass)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct T3dDiagnostic {
+    /// The region of the source text that was skipped.
+    pub span: T3dSpan,
+    pub message: String,
+}
 
-    fn __str__(&self) -> String {
-        self.to_string()
+impl fmt::Display for T3dDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.span, self.message)
     }
 }
 
-#[derive(Debug, Clone)]
+/// An object that could not be read as a typed view, such as a polygon with a vector that does not have three
+/// components.
+#[derive(Debug, Clone, PartialEq)]
+pub struct T3dViewError {
+    pub message: String,
+    /// Where the problem is, if the object was parsed.
+    pub span: Option<T3dSpan>,
+}
+
+impl fmt::Display for T3dViewError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.span {
+            Some(span) => write!(f, "{} at line {}, column {}", self.message, span.start_line, span.start_column),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for T3dViewError {}
+
+/// Values are serialized as `{"type": "<variant>", "value": ...}`, so that the variant survives a round-trip.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", content = "value"))]
 pub enum T3dValue {
-    Int(i32),
+    Int(i64),
     Float(f32),
     Bool(bool),
     String(String),
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_sorted"))]
     Struct(Box<T3dStruct>),
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_sorted"))]
     InlineStruct(Box<T3dStruct>),
     Reference(T3dReference),
     Identifier(String),
@@ -45,43 +161,111 @@ pub enum T3dValue {
     Array(Vec<Option<T3dValue>>)
 }
 
-impl ToString for T3dValue {
-    fn to_string(&self) -> String {
-        match self {
-            T3dValue::Int(value) => value.to_string(),
-            T3dValue::Float(value) => value.to_string(),
-            T3dValue::Bool(value) => value.to_string(),
-            T3dValue::String(value) => format!("\"{}\"", value.clone()),
-            T3dValue::Struct(_) => String::from("Struct()"),
-            T3dValue::InlineStruct(_) => String::from("InlineStruct()"),
-            T3dValue::Reference(value) => value.to_string(),
-            T3dValue::Identifier(value) => value.clone(),
-            T3dValue::Vector(value) => format!("{:?}", value.clone()),
-            T3dValue::Array(_) => String::from("Array()"),
+/// Formats a float the way UnrealEd exports them (e.g. `1.500000`), keeping any extra precision.
+pub fn format_float(value: f32) -> String {
+    let text = value.to_string();
+    match text.split_once('.') {
+        Some((_, fraction)) if fraction.len() > 6 => text,
+        _ => format!("{:.6}", value),
+    }
+}
+
+/// Formats a vector component in the fixed-width style used by polygon vectors (e.g. `+00128.000000`).
+pub fn format_vector_component(value: f32) -> String {
+    format!("{:+013.6}", value)
+}
+
+/// Escapes a string for use inside a quoted T3D string literal.
+pub fn escape_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            _ => escaped.push(c),
         }
     }
+    escaped
 }
 
-impl IntoPy<PyObject> for T3dValue {
-    fn into_py(self, py: Python<'_>) -> PyObject {
-        match self {
-            T3dValue::Int(value) => value.into_py(py),
-            T3dValue::Float(value) => value.into_py(py),
-            T3dValue::Bool(value) => value.into_py(py),
-            T3dValue::String(value) => value.into_py(py),
-            T3dValue::Struct(value) => value.as_ref().clone().into_py(py),
-            T3dValue::InlineStruct(value) => value.as_ref().clone().into_py(py),
-            T3dValue::Reference(value) => value.clone().into_py(py),
-            T3dValue::Identifier(value) => value.into_py(py),
-            T3dValue::Vector(value) => value.into_py(py),
-            T3dValue::Array(value) => value.into_py(py),
+/// Decodes the escape sequences that [`escape_string`] writes. A backslash followed by any other character is kept,
+/// so that paths such as `C:\UT\Maps` survive.
+pub fn unescape_string(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some(c @ ('"' | '\\')) => unescaped.push(c),
+            Some(c) => {
+                unescaped.push('\\');
+                unescaped.push(c);
+            },
+            None => unescaped.push('\\'),
         }
     }
+    unescaped
 }
 
-impl ToPyObject for T3dValue {
-    fn to_object(&self, py: Python<'_>) -> PyObject {
-        self.clone().into_py(py)
+/// Looks up a member of a struct such as `(X=1,Y=2)` without regard to case, preferring an exact match.
+pub fn struct_member<'a>(value: &'a T3dStruct, name: &str) -> Option<&'a T3dValue> {
+    value.get(name).or_else(|| {
+        value.iter().filter(|(key, _)| key.eq_ignore_ascii_case(name)).min_by_key(|(key, _)| *key).map(|(_, value)| value)
+    })
+}
+
+/// Returns the entries of a struct sorted by name, so that output is deterministic.
+pub fn sorted_struct_entries(value: &T3dStruct) -> Vec<(&String, &T3dValue)> {
+    let mut entries: Vec<(&String, &T3dValue)> = value.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    entries
+}
+
+/// Values are displayed using T3D syntax, so that they can be written back out. The alternate form (`{:#}`) writes
+/// strings verbatim, without escapes, as UE1 and UE2 do.
+impl fmt::Display for T3dValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let alternate = f.alternate();
+        let format = |value: &T3dValue| if alternate { format!("{:#}", value) } else { value.to_string() };
+        match self {
+            T3dValue::Int(value) => write!(f, "{}", value),
+            T3dValue::Float(value) => write!(f, "{}", format_float(*value)),
+            T3dValue::Bool(value) => write!(f, "{}", if *value { "True" } else { "False" }),
+            T3dValue::String(value) if alternate => write!(f, "\"{}\"", value),
+            T3dValue::String(value) => write!(f, "\"{}\"", escape_string(value)),
+            T3dValue::Struct(value) => {
+                let entries: Vec<String> = sorted_struct_entries(value).iter()
+                    .map(|(name, value)| format!("{}={}", name, format(value)))
+                    .collect();
+                write!(f, "({})", entries.join(","))
+            },
+            T3dValue::InlineStruct(value) => {
+                let entries: Vec<String> = sorted_struct_entries(value).iter()
+                    .map(|(name, value)| format!("{}={}", name, format(value)))
+                    .collect();
+                write!(f, "{}", entries.join(" "))
+            },
+            T3dValue::Reference(value) => write!(f, "{}", value),
+            T3dValue::Identifier(value) => write!(f, "{}", value),
+            T3dValue::Vector((x, y, z)) => write!(f, "{},{},{}",
+                format_vector_component(*x),
+                format_vector_component(*y),
+                format_vector_component(*z)
+            ),
+            T3dValue::Array(values) => {
+                let entries: Vec<String> = values.iter()
+                    .map(|value| value.as_ref().map(format).unwrap_or_default())
+                    .collect();
+                write!(f, "({})", entries.join(","))
+            },
+        }
     }
 }
 
@@ -98,63 +282,257 @@ pub struct T3dPropertyAssignmentVector {
     pub value: Vec<f32>,
 }
 
-#[derive(Debug, Clone)]
+/// Property values are serialized as `{"type": "Value", "value": <value>}` or, for array properties, as
+/// `{"type": "Array", "value": [[<index or null>, <value>], ...]}`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", content = "value"))]
 pub enum T3dPropertyValue {
     Value(T3dValue),
     Array(Vec<(Option<i32>, T3dValue)>),
 }
 
-impl IntoPy<PyObject> for T3dPropertyValue {
-    fn into_py(self, py: Python<'_>) -> PyObject {
-        match self {
-            T3dPropertyValue::Value(value) => { value.into_py(py) }
-            T3dPropertyValue::Array(array) => { array.into_py(py) }
-        }
-    }
+#[derive(Debug, Clone)]
+pub enum T3dObjectStatement {
+    Object(Box<T3dObject>),
+    PropertyAssignment(T3dPropertyAssignment),
+    PropertyAssignmentVector(T3dPropertyAssignmentVector),
+    CustomProperties(String),
 }
 
-impl ToPyObject for T3dPropertyValue {
-    fn to_object(&self, py: Python<'_>) -> PyObject {
-        self.clone().into_py(py)
-    }
+/// A statement of an object body, as it was laid out in the source text.
+#[derive(Debug, Clone, PartialEq)]
+pub enum T3dLayoutStatement {
+    Property {
+        leading: String,
+        raw: String,
+        name: String,
+        index: Option<i32>,
+        value: T3dValue,
+    },
+    VectorProperty {
+        leading: String,
+        raw: String,
+        name: String,
+        value: Vec<f32>,
+    },
+    CustomProperties {
+        leading: String,
+        raw: String,
+        value: String,
+    },
+    /// Index into the children of the object.
+    Object(usize),
+    /// A duplicate assignment that is not stored in `properties`. It is only written back while the first
+    /// assignment of the property is unchanged.
+    Duplicate {
+        leading: String,
+        raw: String,
+        name: String,
+    },
 }
 
-#[derive(Debug, Clone)]
-pub enum T3dObjectStatement {
-    Object(T3dObject),
-    PropertyAssignment(T3dPropertyAssignment),
-    PropertyAssignmentVector(T3dPropertyAssignmentVector)
+/// Records how an object was laid out in the source text so that it can be written back byte-for-byte.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct T3dLayout {
+    /// Whitespace preceding the `Begin` line.
+    pub leading: String,
+    /// The `Begin` keyword, object type and header attributes.
+    pub begin: String,
+    /// The header attributes as they were parsed, used to detect whether `begin` is still up to date.
+    pub header: Vec<(String, T3dValue)>,
+    pub statements: Vec<T3dLayoutStatement>,
+    /// Whitespace preceding the `End` line, plus the `End` keyword and object type.
+    pub end: String,
+    /// Whitespace following the object (only set for the last top-level object).
+    pub trailing: String,
 }
 
-#[pyclass]
-#[derive(Debug, Clone)]
+/// Only the contents of an object are serialized; its spans and layout are not.
+#[cfg_attr(feature = "python", pyo3::pyclass)]
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct T3dObject {
-    #[pyo3(get)]
+    #[cfg_attr(feature = "serde", serde(rename = "type"))]
     pub type_: String,
-    #[pyo3(get)]
-    pub children: Vec<Box<T3dObject>>,
-    #[pyo3(get)]
+    /// Attributes on the `Begin` line (e.g. `Class`, `Name`), in source order.
+    pub header: Vec<(String, T3dValue)>,
+    pub children: Vec<T3dObject>,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_sorted"))]
     pub properties: HashMap<String, T3dPropertyValue>,
-    #[pyo3(get)]
     pub vector_properties: Vec<(String, Vec<f32>)>,
+    /// The text following the `CustomProperties` keyword of each `CustomProperties` statement, in source order.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Vec::is_empty"))]
+    pub custom_properties: Vec<String>,
+    /// The whole object, from `Begin` to `End`. Spans are only present for objects that were parsed.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub span: Option<T3dSpan>,
+    /// The `Begin` line, including the header attributes.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub begin_span: Option<T3dSpan>,
+    /// The `End` line.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub end_span: Option<T3dSpan>,
+    /// Spans of every stored property assignment, in the same order as the values of array properties.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub property_spans: HashMap<String, Vec<T3dSpan>>,
+    /// Spans of every vector property, in the same order as `vector_properties`.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub vector_property_spans: Vec<T3dSpan>,
+    /// Only present when the object was parsed in lossless mode.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub layout: Option<T3dLayout>,
+    #[cfg(feature = "python")]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) py_links: crate::python::PyLinks,
 }
 
-impl IntoPy<PyObject> for Box<T3dObject> {
-    fn into_py(self, py: Python<'_>) -> PyObject {
-        return self.as_ref().clone().into_py(py)
+/// Objects are equal if their contents are equal, regardless of where they came from.
+impl PartialEq for T3dObject {
+    fn eq(&self, other: &Self) -> bool {
+        self.type_ == other.type_ &&
+            self.header == other.header &&
+            self.children == other.children &&
+            self.properties == other.properties &&
+            self.vector_properties == other.vector_properties &&
+            self.custom_properties == other.custom_properties
     }
 }
 
-#[pymethods]
 impl T3dObject {
-    fn __getitem__(&self, key: String) -> PyResult<T3dPropertyValue> {
-        match self.properties.get(key.as_str()) {
-            None => {
-                Err(PyKeyError::new_err("Property not found"))
-            }
-            Some(property_value) => {
-                Ok(property_value.clone())
+    /// Creates an empty object, e.g. `Actor` for a `Begin Actor` block.
+    pub fn new(type_: &str) -> Self {
+        T3dObject { type_: String::from(type_), ..Default::default() }
+    }
+
+    /// Sets a property, replacing any existing value. The spans of the replaced assignments are forgotten.
+    pub fn set_property(&mut self, name: &str, value: T3dPropertyValue) {
+        self.property_spans.remove(name);
+        self.properties.insert(String::from(name), value);
+    }
+
+    /// Removes a property along with its spans, returning its value.
+    pub fn remove_property(&mut self, name: &str) -> Option<T3dPropertyValue> {
+        self.property_spans.remove(name);
+        self.properties.remove(name)
+    }
+
+    /// Removes and returns the child at `index`. The layout of a lossless object is updated so that the remaining
+    /// children keep their places in the text.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn remove_child(&mut self, index: usize) -> T3dObject {
+        if let Some(layout) = self.layout.as_mut() {
+            layout.statements.retain(|statement| !matches!(statement, T3dLayoutStatement::Object(i) if *i == index));
+            for statement in &mut layout.statements {
+                if let T3dLayoutStatement::Object(i) = statement {
+                    if *i > index {
+                        *i -= 1;
+                    }
+                }
             }
         }
+        self.children.remove(index)
+    }
+
+    /// Returns the name of a property as it is spelled in the object. Like UnrealScript names, `name` is matched
+    /// without regard to case, so `csgoper` finds `CsgOper`. An exact match is preferred if the object has several
+    /// spellings of the name.
+    pub fn property_key(&self, name: &str) -> Option<&str> {
+        match self.properties.get_key_value(name) {
+            Some((key, _)) => Some(key.as_str()),
+            None => self.properties.keys().filter(|key| key.eq_ignore_ascii_case(name)).min().map(String::as_str),
+        }
+    }
+
+    /// Looks up a property without regard to case. Use `properties.get` for an exact match.
+    pub fn property(&self, name: &str) -> Option<&T3dPropertyValue> {
+        self.properties.get(self.property_key(name)?)
+    }
+
+    /// Looks up a property without regard to case for editing, keeping the spelling of its name.
+    pub fn property_mut(&mut self, name: &str) -> Option<&mut T3dPropertyValue> {
+        let key = String::from(self.property_key(name)?);
+        self.properties.get_mut(&key)
+    }
+
+    /// Returns the span of a property assignment, matching the name without regard to case. For array
+    /// properties, `index` selects the element.
+    pub fn property_span(&self, name: &str, index: Option<i32>) -> Option<T3dSpan> {
+        let name = self.property_key(name)?;
+        let spans = self.property_spans.get(name)?;
+        match (self.properties.get(name)?, index) {
+            (T3dPropertyValue::Array(values), Some(index)) => {
+                let position = values.iter().position(|(i, _)| *i == Some(index))?;
+                spans.get(position).copied()
+            },
+            _ => spans.first().copied()
+        }
+    }
+
+    /// Returns the value of a header attribute, e.g. `Class` in `Begin Actor Class=Brush`.
+    pub fn header_value(&self, name: &str) -> Option<&T3dValue> {
+        self.header.iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value)
+    }
+
+    /// Sets a header attribute, matching the name without regard to case. An existing attribute keeps its spelling
+    /// and position, and a new one is appended.
+    pub fn set_header_value(&mut self, name: &str, value: T3dValue) {
+        match self.header.iter_mut().find(|(key, _)| key.eq_ignore_ascii_case(name)) {
+            Some((_, existing)) => *existing = value,
+            None => self.header.push((String::from(name), value)),
+        }
+    }
+
+    /// Removes a header attribute, matching the name without regard to case, returning its value.
+    pub fn remove_header_value(&mut self, name: &str) -> Option<T3dValue> {
+        let position = self.header.iter().position(|(key, _)| key.eq_ignore_ascii_case(name))?;
+        Some(self.header.remove(position).1)
+    }
+
+    /// Returns the value of a header attribute as a string, without quotes.
+    pub fn header_string(&self, name: &str) -> Option<String> {
+        match self.header_value(name)? {
+            T3dValue::String(value) | T3dValue::Identifier(value) => Some(value.clone()),
+            value => Some(value.to_string())
+        }
+    }
+
+    pub fn class_name(&self) -> Option<String> {
+        self.header_string("Class")
+    }
+
+    pub fn name(&self) -> Option<String> {
+        self.header_string("Name")
+    }
+
+    /// Returns the `Archetype` header attribute of a UE3/UE4 actor or subobject.
+    pub fn archetype(&self) -> Option<&T3dReference> {
+        match self.header_value("Archetype")? {
+            T3dValue::Reference(reference) => Some(reference),
+            _ => None
+        }
+    }
+
+    /// Returns the `Begin Object` blocks nested in this object, such as the components of a UE3/UE4 actor.
+    pub fn subobjects(&self) -> impl Iterator<Item = &T3dObject> {
+        self.children.iter().filter(|child| child.type_.eq_ignore_ascii_case("Object"))
+    }
+
+    /// Returns the subobject that is referred to by `name`, which may be either its `Name` or, for UE3, its
+    /// `ObjName`. UE4 declares a subobject with its class and archetype in an empty block, and then defines its
+    /// properties in a second block with the same name; the definition is returned in that case.
+    pub fn subobject(&self, name: &str) -> Option<&T3dObject> {
+        self.subobjects()
+            .filter(|subobject| {
+                ["Name", "ObjName"].iter()
+                    .filter_map(|attribute| subobject.header_string(attribute))
+                    .any(|n| n.eq_ignore_ascii_case(name))
+            })
+            .last()
     }
 }
@@ -0,0 +1,221 @@
+//! Command-line tool for inspecting, validating and converting T3D files.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::ExitCode;
+use t3d::encoding::decode;
+use t3d::encoding::encode;
+use t3d::parser::read_t3d_file_with_options;
+use t3d::{export_obj_with_options, parse_t3d_recovering, read_t3d_file, write_t3d, write_t3d_with_dialect, T3dDialect, T3dEncoding, T3dExportOptions, T3dObject, T3dParseOptions};
+
+const USAGE: &str = "Usage: t3d <command> [options] <file>...
+
+Commands:
+    check <file>...              Parse files and report every syntax error with its line number
+    stats <file>...              Count actors by class, polygons and textures, and detect the engine dialect
+    dump [--json] <file>         Print the object tree, or the objects as JSON
+    fmt [--dialect <dialect>] [-o <output>] <file>
+                                 Re-emit a file as normalized T3D, read and written as UE1, UE2, UE2.5, UE3 or
+                                 UE4 if a dialect is given
+    obj [--csg] [-o <output>] <file>
+                                 Export brush geometry to OBJ, next to the file unless an output is given, or
+                                 with --csg, the level geometry built from the brushes
+    glb [-o <output>] <file>     Export actors and brush geometry to binary glTF";
+
+fn read(path: &str) -> Result<String, String> {
+    let bytes = std::fs::read(path).map_err(|err| format!("{}: {}", path, err))?;
+    Ok(decode(bytes.as_slice()).0)
+}
+
+fn parse(path: &str) -> Result<(Vec<T3dObject>, T3dEncoding), String> {
+    read_t3d_file(path).map_err(|err| err.to_string())
+}
+
+fn check(paths: &[String]) -> Result<(), String> {
+    let mut failed = false;
+    for path in paths {
+        let contents = read(path)?;
+        let (_, diagnostics) = parse_t3d_recovering(contents.as_str(), T3dParseOptions::default());
+        if diagnostics.is_empty() {
+            println!("{}: ok", path);
+        }
+        for diagnostic in &diagnostics {
+            println!("{}:{}: {}", path, diagnostic.span, diagnostic.message);
+        }
+        failed |= !diagnostics.is_empty();
+    }
+    if failed {
+        Err(String::from("some files contain errors"))
+    } else {
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct Stats {
+    actors: BTreeMap<String, usize>,
+    polygons: usize,
+    textures: BTreeMap<String, usize>,
+}
+
+impl Stats {
+    fn add(&mut self, object: &T3dObject) {
+        if object.type_.eq_ignore_ascii_case("Actor") {
+            let class = object.class_name().unwrap_or_else(|| String::from("<none>"));
+            *self.actors.entry(class).or_default() += 1;
+        } else if object.type_.eq_ignore_ascii_case("Polygon") {
+            self.polygons += 1;
+            if let Some(texture) = object.header_string("Texture") {
+                *self.textures.entry(texture).or_default() += 1;
+            }
+        }
+        for child in &object.children {
+            self.add(child);
+        }
+    }
+
+    fn print(&self, dialect: Option<T3dDialect>) {
+        println!("Dialect: {}", dialect.map_or("unknown", |dialect| dialect.name()));
+        println!("Actors: {}", self.actors.values().sum::<usize>());
+        for (class, count) in &self.actors {
+            println!("    {:<40} {}", class, count);
+        }
+        println!("Polygons: {}", self.polygons);
+        println!("Textures: {}", self.textures.len());
+        for (texture, count) in &self.textures {
+            println!("    {:<40} {}", texture, count);
+        }
+    }
+}
+
+fn stats(paths: &[String]) -> Result<(), String> {
+    for path in paths {
+        let mut stats = Stats::default();
+        let (objects, _) = parse(path)?;
+        for object in &objects {
+            stats.add(object);
+        }
+        if paths.len() > 1 {
+            println!("{}:", path);
+        }
+        stats.print(T3dDialect::detect(&objects));
+    }
+    Ok(())
+}
+
+fn print_tree(object: &T3dObject, depth: usize) {
+    let mut line = format!("{}{}", "    ".repeat(depth), object.type_);
+    for (name, value) in &object.header {
+        line.push_str(format!(" {}={}", name, value).as_str());
+    }
+    if let Some(span) = object.span {
+        line.push_str(format!(" (line {})", span.start_line).as_str());
+    }
+    println!("{}", line);
+    for child in &object.children {
+        print_tree(child, depth + 1);
+    }
+}
+
+fn dump(path: &str, json: bool) -> Result<(), String> {
+    let (objects, _) = parse(path)?;
+    if json {
+        #[cfg(feature = "serde")]
+        println!("{}", t3d::json::to_json_pretty(&objects).map_err(|err| err.to_string())?);
+        #[cfg(not(feature = "serde"))]
+        return Err(String::from("JSON output requires the `serde` feature"));
+    } else {
+        for object in &objects {
+            print_tree(object, 0);
+        }
+    }
+    Ok(())
+}
+
+/// Files are written in the encoding they were read in, while standard output is always UTF-8.
+fn fmt(path: &str, output: Option<&str>, dialect: Option<T3dDialect>) -> Result<(), String> {
+    let options = T3dParseOptions { dialect, ..Default::default() };
+    let (objects, encoding) = read_t3d_file_with_options(path, options).map_err(|err| err.to_string())?;
+    let contents = match dialect {
+        Some(dialect) => write_t3d_with_dialect(&objects, dialect),
+        None => write_t3d(&objects),
+    };
+    match output {
+        Some(output) => std::fs::write(output, encode(contents.as_str(), encoding)).map_err(|err| format!("{}: {}", output, err)),
+        None => {
+            print!("{}", contents);
+            Ok(())
+        }
+    }
+}
+
+fn obj(path: &str, output: Option<&str>, csg: bool) -> Result<(), String> {
+    let (objects, _) = parse(path)?;
+    let output = output.map_or_else(|| Path::new(path).with_extension("obj"), |output| Path::new(output).to_path_buf());
+    let options = T3dExportOptions { csg, ..Default::default() };
+    export_obj_with_options(&objects, &output, &options).map_err(|err| format!("{}: {}", output.display(), err))?;
+    println!("{}", output.display());
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+fn glb(path: &str, output: Option<&str>) -> Result<(), String> {
+    let (objects, _) = parse(path)?;
+    let output = output.map_or_else(|| Path::new(path).with_extension("glb"), |output| Path::new(output).to_path_buf());
+    t3d::export_glb(&objects, &output).map_err(|err| format!("{}: {}", output.display(), err))?;
+    println!("{}", output.display());
+    Ok(())
+}
+
+#[cfg(not(feature = "serde"))]
+fn glb(_path: &str, _output: Option<&str>) -> Result<(), String> {
+    Err(String::from("glTF output requires the `serde` feature"))
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let (command, args) = args.split_first().ok_or(USAGE)?;
+    let mut paths = Vec::new();
+    let mut json = false;
+    let mut csg = false;
+    let mut output = None;
+    let mut dialect = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" if command == "dump" => json = true,
+            "--csg" if command == "obj" => csg = true,
+            "--dialect" if command == "fmt" => {
+                let name = args.next().ok_or("missing value for --dialect")?;
+                dialect = Some(T3dDialect::from_name(name).ok_or_else(|| format!("unknown dialect: {}", name))?)
+            },
+            "-o" | "--output" if ["fmt", "obj", "glb"].contains(&command.as_str()) => {
+                output = Some(args.next().ok_or("missing value for --output")?.as_str())
+            },
+            _ if arg.starts_with('-') => return Err(format!("unknown option: {}\n\n{}", arg, USAGE)),
+            _ => paths.push(arg.clone()),
+        }
+    }
+    if paths.is_empty() {
+        return Err(String::from(USAGE))
+    }
+    match (command.as_str(), paths.as_slice()) {
+        ("check", paths) => check(paths),
+        ("stats", paths) => stats(paths),
+        ("dump", [path]) => dump(path, json),
+        ("fmt", [path]) => fmt(path, output, dialect),
+        ("obj", [path]) => obj(path, output, csg),
+        ("glb", [path]) => glb(path, output),
+        _ => Err(String::from(USAGE)),
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
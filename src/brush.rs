@@ -0,0 +1,426 @@
+//! A typed view of brush actors.
+
+use crate::ast::{struct_member, T3dObject, T3dPropertyValue, T3dSpan, T3dStruct, T3dValue, T3dViewError};
+use crate::dialect::T3dDialect;
+use crate::flags::{PolyFlags, PolyFlagsLayout};
+use crate::polygon::{T3dPolygon, T3dVector};
+
+/// The CSG operation of a brush, from its `CsgOper` property.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CsgOper {
+    /// The builder brush, which is not part of the level geometry.
+    #[default]
+    Active,
+    Add,
+    Subtract,
+    Intersect,
+    Deintersect,
+}
+
+/// The axis that a brush scale is sheared along, from the `SheerAxis` member of `MainScale` and `PostScale`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SheerAxis {
+    #[default]
+    None,
+    XY,
+    XZ,
+    YX,
+    YZ,
+    ZX,
+    ZY,
+}
+
+const CSG_OPERS: [(CsgOper, &str); 5] = [
+    (CsgOper::Active, "CSG_Active"),
+    (CsgOper::Add, "CSG_Add"),
+    (CsgOper::Subtract, "CSG_Subtract"),
+    (CsgOper::Intersect, "CSG_Intersect"),
+    (CsgOper::Deintersect, "CSG_Deintersect"),
+];
+
+const SHEER_AXES: [(SheerAxis, &str); 7] = [
+    (SheerAxis::None, "SHEER_None"),
+    (SheerAxis::XY, "SHEER_XY"),
+    (SheerAxis::XZ, "SHEER_XZ"),
+    (SheerAxis::YX, "SHEER_YX"),
+    (SheerAxis::YZ, "SHEER_YZ"),
+    (SheerAxis::ZX, "SHEER_ZX"),
+    (SheerAxis::ZY, "SHEER_ZY"),
+];
+
+impl CsgOper {
+    /// Returns the name used in T3D files, e.g. `CSG_Add`.
+    pub fn name(&self) -> &'static str {
+        CSG_OPERS.iter().find(|(oper, _)| oper == self).map(|(_, name)| *name).unwrap_or_default()
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        CSG_OPERS.iter().find(|(_, n)| n.eq_ignore_ascii_case(name)).map(|(oper, _)| *oper)
+    }
+}
+
+impl SheerAxis {
+    /// Returns the name used in T3D files, e.g. `SHEER_ZX`.
+    pub fn name(&self) -> &'static str {
+        SHEER_AXES.iter().find(|(axis, _)| axis == self).map(|(_, name)| *name).unwrap_or_default()
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        SHEER_AXES.iter().find(|(_, n)| n.eq_ignore_ascii_case(name)).map(|(axis, _)| *axis)
+    }
+}
+
+/// A brush scale, from the `MainScale` and `PostScale` properties.
+#[cfg_attr(feature = "python", pyo3::pyclass)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct T3dScale {
+    pub scale: T3dVector,
+    pub sheer_rate: f32,
+    pub sheer_axis: SheerAxis,
+}
+
+impl Default for T3dScale {
+    fn default() -> Self {
+        T3dScale { scale: [1.0; 3], sheer_rate: 0.0, sheer_axis: SheerAxis::None }
+    }
+}
+
+/// A brush actor, such as `Begin Actor Class=Brush`, along with the polygons of its `Begin Brush` model.
+///
+/// Properties that are missing from the actor have their default values: no offset, rotation or pivot, and a
+/// scale of one.
+#[cfg_attr(feature = "python", pyo3::pyclass)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct T3dBrush {
+    pub name: Option<String>,
+    pub csg_oper: CsgOper,
+    pub location: T3dVector,
+    /// The pitch, yaw and roll, in Unreal rotation units (65536 per turn).
+    pub rotation: T3dVector,
+    pub pre_pivot: T3dVector,
+    pub main_scale: T3dScale,
+    pub post_scale: T3dScale,
+    pub poly_flags: PolyFlags,
+    /// The layout that `poly_flags` and the flags of the polygons were read with.
+    pub flags_layout: PolyFlagsLayout,
+    pub polygons: Vec<T3dPolygon>,
+}
+
+/// Looks up a struct property, along with the span of its assignment.
+pub(crate) fn struct_property<'a>(object: &'a T3dObject, name: &str) -> Result<Option<(&'a T3dStruct, Option<T3dSpan>)>, T3dViewError> {
+    let span = object.property_span(name, None);
+    match object.property(name) {
+        None => Ok(None),
+        Some(T3dPropertyValue::Value(T3dValue::Struct(value))) => Ok(Some((value, span))),
+        Some(_) => Err(T3dViewError { message: format!("{} must be a struct", name), span }),
+    }
+}
+
+pub(crate) fn number(value: &T3dValue, name: &str, span: Option<T3dSpan>) -> Result<f32, T3dViewError> {
+    match value {
+        T3dValue::Int(value) => Ok(*value as f32),
+        T3dValue::Float(value) => Ok(*value),
+        value => Err(T3dViewError { message: format!("{} must be a number, found {}", name, value), span }),
+    }
+}
+
+/// Reads the numeric members of a struct such as `(X=1,Y=2,Z=3)`, with missing members defaulting to `default`.
+pub(crate) fn components(value: &T3dStruct, names: [&str; 3], default: f32, span: Option<T3dSpan>) -> Result<T3dVector, T3dViewError> {
+    let mut vector = [default; 3];
+    for (component, name) in vector.iter_mut().zip(names) {
+        if let Some(value) = struct_member(value, name) {
+            *component = number(value, name, span)?;
+        }
+    }
+    Ok(vector)
+}
+
+pub(crate) fn vector_property(object: &T3dObject, name: &str, names: [&str; 3]) -> Result<T3dVector, T3dViewError> {
+    match struct_property(object, name)? {
+        Some((value, span)) => components(value, names, 0.0, span),
+        None => Ok([0.0; 3]),
+    }
+}
+
+fn scale_property(object: &T3dObject, name: &str) -> Result<T3dScale, T3dViewError> {
+    let Some((value, span)) = struct_property(object, name)? else {
+        return Ok(T3dScale::default())
+    };
+    let scale = match struct_member(value, "Scale") {
+        Some(T3dValue::Struct(scale)) => components(scale, ["X", "Y", "Z"], 1.0, span)?,
+        Some(value) => return Err(T3dViewError { message: format!("Scale must be a struct, found {}", value), span }),
+        None => [1.0; 3],
+    };
+    let sheer_rate = match struct_member(value, "SheerRate") {
+        Some(value) => number(value, "SheerRate", span)?,
+        None => 0.0,
+    };
+    let sheer_axis = match struct_member(value, "SheerAxis") {
+        Some(T3dValue::Identifier(axis)) => SheerAxis::from_name(axis)
+            .ok_or_else(|| T3dViewError { message: format!("Unknown SheerAxis {}", axis), span })?,
+        Some(value) => return Err(T3dViewError { message: format!("Unknown SheerAxis {}", value), span }),
+        None => SheerAxis::None,
+    };
+    Ok(T3dScale { scale, sheer_rate, sheer_axis })
+}
+
+const COMPONENT_TRANSFORM: [&str; 3] = ["RelativeLocation", "RelativeRotation", "RelativeScale3D"];
+
+/// Reads the location, rotation and scale of a UE4 actor from its root component: the subobject named by
+/// `RootComponent`, or else the first subobject with a `RelativeLocation`, `RelativeRotation` or `RelativeScale3D`.
+/// The rotation is converted from degrees to Unreal rotation units. Returns `None` if there is no such subobject.
+pub(crate) fn component_transform(object: &T3dObject) -> Result<Option<(T3dVector, T3dVector, T3dVector)>, T3dViewError> {
+    let root = match object.property("RootComponent") {
+        Some(T3dPropertyValue::Value(T3dValue::String(name) | T3dValue::Identifier(name))) => Some(name.as_str()),
+        Some(T3dPropertyValue::Value(T3dValue::Reference(reference))) => reference.path.rsplit(['.', ':']).next(),
+        _ => None,
+    };
+    let candidates: Vec<&T3dObject> = object.children.iter()
+        .filter(|child| child.type_.eq_ignore_ascii_case("Object"))
+        .filter(|child| COMPONENT_TRANSFORM.iter().any(|name| child.property(name).is_some()))
+        .collect();
+    let component = candidates.iter()
+        .find(|child| root.is_some() && child.name().as_deref() == root)
+        .or_else(|| candidates.first());
+    let Some(component) = component else {
+        return Ok(None)
+    };
+    let location = vector_property(component, "RelativeLocation", ["X", "Y", "Z"])?;
+    let rotation = vector_property(component, "RelativeRotation", ["Pitch", "Yaw", "Roll"])?;
+    let rotation = rotation.map(|angle| angle * 65536.0 / T3dDialect::Ue4.rotation_units_per_turn());
+    let scale = match struct_property(component, "RelativeScale3D")? {
+        Some((value, span)) => components(value, ["X", "Y", "Z"], 1.0, span)?,
+        None => [1.0; 3],
+    };
+    Ok(Some((location, rotation, scale)))
+}
+
+fn csg_oper(object: &T3dObject) -> Result<CsgOper, T3dViewError> {
+    let span = object.property_span("CsgOper", None);
+    match object.property("CsgOper") {
+        None => Ok(CsgOper::Active),
+        Some(T3dPropertyValue::Value(T3dValue::Identifier(oper))) => CsgOper::from_name(oper)
+            .ok_or_else(|| T3dViewError { message: format!("Unknown CsgOper {}", oper), span }),
+        Some(_) => Err(T3dViewError { message: String::from("CsgOper must be an identifier"), span }),
+    }
+}
+
+/// A 3x3 matrix that transforms row vectors, as Unreal's matrices do.
+pub(crate) type Matrix = [[f64; 3]; 3];
+
+const IDENTITY: Matrix = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut m = [[0.0; 3]; 3];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    m
+}
+
+pub(crate) fn transform(v: T3dVector, m: &Matrix) -> [f64; 3] {
+    let v = v.map(f64::from);
+    [0, 1, 2].map(|j| v[0] * m[0][j] + v[1] * m[1][j] + v[2] * m[2][j])
+}
+
+fn determinant(m: &Matrix) -> f64 {
+    m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+}
+
+/// The transpose of the inverse, which transforms normals and texture axes so that their dot products with
+/// transformed points are preserved.
+fn inverse_transpose(m: &Matrix) -> Matrix {
+    let det = determinant(m);
+    let cofactor = |i: usize, j: usize| {
+        let (r0, r1, c0, c1) = ((i + 1) % 3, (i + 2) % 3, (j + 1) % 3, (j + 2) % 3);
+        (m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]) / det
+    };
+    [[cofactor(0, 0), cofactor(0, 1), cofactor(0, 2)],
+     [cofactor(1, 0), cofactor(1, 1), cofactor(1, 2)],
+     [cofactor(2, 0), cofactor(2, 1), cofactor(2, 2)]]
+}
+
+/// Rounds a shear rate the way UnrealEd does before applying it.
+fn sheer_snap(sheer: f64) -> f64 {
+    match sheer {
+        s if s < -0.65 => s + 0.15,
+        s if s > 0.65 => s - 0.15,
+        s if s < -0.55 => -0.5,
+        s if s > 0.55 => 0.5,
+        s if s < -0.05 => s + 0.05,
+        s if s > 0.05 => s - 0.05,
+        _ => 0.0,
+    }
+}
+
+impl T3dScale {
+    /// The scale followed by the shear, where `SHEER_XY` moves X in proportion to Y, and so on.
+    fn matrix(&self) -> Matrix {
+        let mut sheer = IDENTITY;
+        let rate = sheer_snap(self.sheer_rate.into());
+        match self.sheer_axis {
+            SheerAxis::None => {},
+            SheerAxis::XY => sheer[1][0] = rate,
+            SheerAxis::XZ => sheer[2][0] = rate,
+            SheerAxis::YX => sheer[0][1] = rate,
+            SheerAxis::YZ => sheer[2][1] = rate,
+            SheerAxis::ZX => sheer[0][2] = rate,
+            SheerAxis::ZY => sheer[1][2] = rate,
+        }
+        let [x, y, z] = self.scale.map(f64::from);
+        multiply(&[[x, 0.0, 0.0], [0.0, y, 0.0], [0.0, 0.0, z]], &sheer)
+    }
+}
+
+/// The rotation matrix of a pitch, yaw and roll in Unreal rotation units, in the form of `FRotationMatrix`.
+pub(crate) fn rotation_matrix(rotation: T3dVector) -> Matrix {
+    let [pitch, yaw, roll] = rotation.map(|angle| f64::from(angle) * std::f64::consts::TAU / 65536.0);
+    let (sp, cp) = pitch.sin_cos();
+    let (sy, cy) = yaw.sin_cos();
+    let (sr, cr) = roll.sin_cos();
+    [[cp * cy, cp * sy, sp],
+     [sr * sp * cy - cr * sy, sr * sp * sy + cr * cy, -sr * cp],
+     [-(cr * sp * cy + sr * sy), cy * sr - cr * sp * sy, cr * cp]]
+}
+
+fn normalize(v: [f64; 3]) -> T3dVector {
+    let length = v.iter().map(|c| c * c).sum::<f64>().sqrt();
+    if length > 0.0 {
+        v.map(|c| (c / length) as f32)
+    } else {
+        v.map(|c| c as f32)
+    }
+}
+
+fn poly_flags(object: &T3dObject) -> Result<PolyFlags, T3dViewError> {
+    match object.property("PolyFlags") {
+        None => Ok(PolyFlags::default()),
+        Some(T3dPropertyValue::Value(T3dValue::Int(flags))) => Ok(PolyFlags(*flags as u32)),
+        Some(_) => Err(T3dViewError {
+            message: String::from("PolyFlags must be an integer"),
+            span: object.property_span("PolyFlags", None),
+        }),
+    }
+}
+
+impl T3dBrush {
+    /// Reads a brush from an actor with a `Begin Brush` model, such as a `Brush`, `Mover` or volume.
+    pub fn from_object(object: &T3dObject) -> Result<Self, T3dViewError> {
+        T3dBrush::from_object_with_layout(object, PolyFlagsLayout::default())
+    }
+
+    /// Reads a brush whose `PolyFlags` and polygon `Flags` use the given layout.
+    pub fn from_object_with_layout(object: &T3dObject, flags_layout: PolyFlagsLayout) -> Result<Self, T3dViewError> {
+        let model = object.children.iter()
+            .find(|child| child.type_.eq_ignore_ascii_case("Brush"))
+            .ok_or_else(|| T3dViewError {
+                message: format!("{} has no Brush model", object.name().unwrap_or_else(|| object.type_.clone())),
+                span: object.begin_span,
+            })?;
+        let polygons = model.children.iter()
+            .filter(|child| child.type_.eq_ignore_ascii_case("PolyList"))
+            .flat_map(|poly_list| poly_list.children.iter())
+            .filter(|child| child.type_.eq_ignore_ascii_case("Polygon"))
+            .map(|polygon| T3dPolygon::from_object_with_layout(polygon, flags_layout))
+            .collect::<Result<Vec<T3dPolygon>, T3dViewError>>()?;
+        Ok(T3dBrush {
+            name: object.name(),
+            csg_oper: csg_oper(object)?,
+            location: vector_property(object, "Location", ["X", "Y", "Z"])?,
+            rotation: vector_property(object, "Rotation", ["Pitch", "Yaw", "Roll"])?,
+            pre_pivot: vector_property(object, "PrePivot", ["X", "Y", "Z"])?,
+            main_scale: scale_property(object, "MainScale")?,
+            post_scale: scale_property(object, "PostScale")?,
+            poly_flags: poly_flags(object)?,
+            flags_layout,
+            polygons,
+        })
+    }
+
+    /// Reads a brush exported by the editor of a dialect, with the flags layout of the dialect. UE4 brushes are
+    /// placed by their component, so their location, rotation and scale are read from the `RelativeLocation`,
+    /// `RelativeRotation` (in degrees) and `RelativeScale3D` of the subobject that has them.
+    pub fn from_object_with_dialect(object: &T3dObject, dialect: T3dDialect) -> Result<Self, T3dViewError> {
+        let mut brush = T3dBrush::from_object_with_layout(object, dialect.poly_flags_layout())?;
+        if dialect == T3dDialect::Ue4 {
+            if let Some((location, rotation, scale)) = component_transform(object)? {
+                brush.location = location;
+                brush.rotation = rotation;
+                brush.main_scale.scale = scale;
+            }
+        }
+        Ok(brush)
+    }
+
+    /// Writes the `PolyFlags` back to the actor that the brush was read from, along with the flags of its
+    /// polygons. Only values that changed are written, and `PolyFlags` is removed when no flags are set.
+    pub fn apply_to(&self, object: &mut T3dObject) -> Result<(), T3dViewError> {
+        let begin_span = object.begin_span;
+        let model = object.children.iter_mut()
+            .find(|child| child.type_.eq_ignore_ascii_case("Brush"))
+            .ok_or_else(|| T3dViewError { message: String::from("Object has no Brush model"), span: begin_span })?;
+        let mut polygons: Vec<&mut T3dObject> = model.children.iter_mut()
+            .filter(|child| child.type_.eq_ignore_ascii_case("PolyList"))
+            .flat_map(|poly_list| poly_list.children.iter_mut())
+            .filter(|child| child.type_.eq_ignore_ascii_case("Polygon"))
+            .collect();
+        if polygons.len() != self.polygons.len() {
+            return Err(T3dViewError {
+                message: format!("Brush has {} polygons, but the model has {}", self.polygons.len(), polygons.len()),
+                span: begin_span,
+            })
+        }
+        for (polygon, target) in self.polygons.iter().zip(polygons.iter_mut()) {
+            polygon.apply_to(target);
+        }
+        if poly_flags(object).ok() != Some(self.poly_flags) {
+            let name = String::from(object.property_key("PolyFlags").unwrap_or("PolyFlags"));
+            if self.poly_flags == PolyFlags::default() {
+                object.remove_property(name.as_str());
+            } else {
+                let value = T3dValue::Int(self.poly_flags.bits() as i32 as i64);
+                object.set_property(name.as_str(), T3dPropertyValue::Value(value));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the polygons in world space, transformed the way UnrealEd does when it rebuilds geometry: the
+    /// `PrePivot` is subtracted, then `MainScale` (including its shear), `Rotation` and `PostScale` are applied,
+    /// and the `Location` is added.
+    ///
+    /// Normals and texture axes are transformed by the inverse transpose so that they stay perpendicular to the
+    /// polygon and keep its texture coordinates, and normals are renormalized. Scales that mirror the brush reverse
+    /// the vertex order, so that polygons still face outwards.
+    pub fn world_polygons(&self) -> Vec<T3dPolygon> {
+        let point_xform = multiply(
+            &multiply(&self.main_scale.matrix(), &rotation_matrix(self.rotation)),
+            &self.post_scale.matrix(),
+        );
+        let vector_xform = inverse_transpose(&point_xform);
+        let mirrored = determinant(&point_xform) < 0.0;
+        let to_world = |vertex: T3dVector| {
+            let local = [0, 1, 2].map(|i| vertex[i] - self.pre_pivot[i]);
+            let world = transform(local, &point_xform);
+            [0, 1, 2].map(|i| (world[i] + f64::from(self.location[i])) as f32)
+        };
+        self.polygons.iter().map(|polygon| {
+            let mut vertices: Vec<T3dVector> = polygon.vertices.iter().copied().map(to_world).collect();
+            if mirrored {
+                vertices.reverse();
+            }
+            T3dPolygon {
+                origin: to_world(polygon.origin),
+                normal: normalize(transform(polygon.normal, &vector_xform)),
+                texture_u: transform(polygon.texture_u, &vector_xform).map(|c| c as f32),
+                texture_v: transform(polygon.texture_v, &vector_xform).map(|c| c as f32),
+                vertices,
+                ..polygon.clone()
+            }
+        }).collect()
+    }
+}
@@ -0,0 +1,390 @@
+//! Constructive solid geometry of the brush list, producing the level geometry that UnrealEd builds.
+//!
+//! Levels start out as solid space. Brushes are replayed in file order: subtractive brushes carve out empty space
+//! and additive brushes fill it back in, so a point is empty if the last brush that contains it is subtractive. The
+//! level geometry is the boundary between empty and solid space, facing into the empty space.
+//!
+//! Every brush polygon is split by the BSP trees of the brushes that overlap it, which also handles brushes that
+//! are not convex, such as stairs. A piece of a polygon is kept if the points just in front of and behind it differ
+//! in whether they are empty. Where polygons of several brushes lie on top of each other, only the piece of the
+//! last brush is kept.
+//!
+//! The builder brush is skipped, as are movers and volumes, which are brushes that are not part of the level
+//! geometry. Brushes with `CsgOper=CSG_Active` are skipped too, as are intersecting and
+//! deintersecting brushes, which UnrealEd only uses to create new brushes. Additive brushes with the semisolid or non-solid `PolyFlags`, and sheets that do not enclose a volume,
+//! do not change the empty space; their polygons are kept wherever they face into empty space.
+
+use crate::ast::{T3dObject, T3dViewError};
+use crate::brush::{CsgOper, T3dBrush};
+use crate::flags::PolyFlags;
+use crate::polygon::T3dPolygon;
+
+/// The distance in Unreal units within which a point is considered to be on a plane.
+const EPSILON: f64 = 0.01;
+/// The distance in Unreal units in front of and behind a polygon at which space is tested for being empty.
+const PROBE: f64 = 0.1;
+
+type Vector = [f64; 3];
+
+fn dot(a: Vector, b: Vector) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn lerp(a: Vector, b: Vector, t: f64) -> Vector {
+    [0, 1, 2].map(|i| a[i] + (b[i] - a[i]) * t)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Plane {
+    normal: Vector,
+    w: f64,
+}
+
+impl Plane {
+    fn flip(&mut self) {
+        self.normal = self.normal.map(|c| -c);
+        self.w = -self.w;
+    }
+}
+
+/// A convex polygon, along with the index of the brush polygon it was split from.
+#[derive(Debug, Clone)]
+struct Polygon {
+    vertices: Vec<Vector>,
+    plane: Plane,
+    source: usize,
+}
+
+/// The normal of a polygon scaled by its area, computed with Newell's method.
+fn area_vector(vertices: &[Vector]) -> Vector {
+    let mut normal = [0.0; 3];
+    for (i, a) in vertices.iter().enumerate() {
+        let b = vertices[(i + 1) % vertices.len()];
+        normal[0] += (a[1] - b[1]) * (a[2] + b[2]);
+        normal[1] += (a[2] - b[2]) * (a[0] + b[0]);
+        normal[2] += (a[0] - b[0]) * (a[1] + b[1]);
+    }
+    normal.map(|c| c / 2.0)
+}
+
+fn length(v: Vector) -> f64 {
+    dot(v, v).sqrt()
+}
+
+impl Polygon {
+    fn new(vertices: Vec<Vector>, source: usize) -> Option<Self> {
+        let area = area_vector(&vertices);
+        if length(area) < EPSILON {
+            return None
+        }
+        let normal = area.map(|c| c / length(area));
+        let w = vertices.iter().map(|&v| dot(normal, v)).sum::<f64>() / vertices.len() as f64;
+        Some(Polygon { vertices, plane: Plane { normal, w }, source })
+    }
+
+    fn flip(&mut self) {
+        self.vertices.reverse();
+        self.plane.flip();
+    }
+}
+
+const COPLANAR: u8 = 0;
+const FRONT: u8 = 1;
+const BACK: u8 = 2;
+const SPANNING: u8 = 3;
+
+/// Where the parts of polygons that were split by a plane end up.
+#[derive(Default)]
+struct Split {
+    coplanar_front: Vec<Polygon>,
+    coplanar_back: Vec<Polygon>,
+    front: Vec<Polygon>,
+    back: Vec<Polygon>,
+}
+
+impl Split {
+    fn add(&mut self, plane: &Plane, polygon: Polygon) {
+        let types: Vec<u8> = polygon.vertices.iter().map(|&v| {
+            let t = dot(plane.normal, v) - plane.w;
+            if t < -EPSILON {
+                BACK
+            } else if t > EPSILON {
+                FRONT
+            } else {
+                COPLANAR
+            }
+        }).collect();
+        match types.iter().fold(COPLANAR, |a, b| a | b) {
+            COPLANAR if dot(plane.normal, polygon.plane.normal) > 0.0 => self.coplanar_front.push(polygon),
+            COPLANAR => self.coplanar_back.push(polygon),
+            FRONT => self.front.push(polygon),
+            BACK => self.back.push(polygon),
+            _ => {
+                let mut front = Vec::new();
+                let mut back = Vec::new();
+                let count = polygon.vertices.len();
+                for i in 0..count {
+                    let j = (i + 1) % count;
+                    let (ti, tj) = (types[i], types[j]);
+                    let (vi, vj) = (polygon.vertices[i], polygon.vertices[j]);
+                    if ti != BACK {
+                        front.push(vi);
+                    }
+                    if ti != FRONT {
+                        back.push(vi);
+                    }
+                    if ti | tj == SPANNING {
+                        let t = (plane.w - dot(plane.normal, vi)) / dot(plane.normal, [0, 1, 2].map(|k| vj[k] - vi[k]));
+                        let v = lerp(vi, vj, t);
+                        front.push(v);
+                        back.push(v);
+                    }
+                }
+                if front.len() >= 3 {
+                    self.front.push(Polygon { vertices: front, ..polygon.clone() });
+                }
+                if back.len() >= 3 {
+                    self.back.push(Polygon { vertices: back, ..polygon });
+                }
+            },
+        }
+    }
+}
+
+/// A BSP tree of a solid, where the back of every plane is inside.
+#[derive(Default)]
+struct Node {
+    plane: Option<Plane>,
+    front: Option<Box<Node>>,
+    back: Option<Box<Node>>,
+}
+
+impl Node {
+    fn new(polygons: Vec<Polygon>) -> Self {
+        let mut node = Node::default();
+        node.build(polygons);
+        node
+    }
+
+    fn build(&mut self, polygons: Vec<Polygon>) {
+        let Some(first) = polygons.first() else {
+            return
+        };
+        let plane = *self.plane.get_or_insert(first.plane);
+        let mut split = Split::default();
+        for polygon in polygons {
+            split.add(&plane, polygon);
+        }
+        if !split.front.is_empty() {
+            self.front.get_or_insert_with(Box::default).build(split.front);
+        }
+        if !split.back.is_empty() {
+            self.back.get_or_insert_with(Box::default).build(split.back);
+        }
+    }
+
+    fn contains(&self, point: Vector) -> bool {
+        let Some(plane) = &self.plane else {
+            return false
+        };
+        if dot(plane.normal, point) - plane.w >= 0.0 {
+            self.front.as_ref().is_some_and(|front| front.contains(point))
+        } else {
+            self.back.as_ref().is_none_or(|back| back.contains(point))
+        }
+    }
+
+    /// Splits a polygon into the pieces that are in the leaves of the tree.
+    fn partition(&self, polygon: Polygon, pieces: &mut Vec<Polygon>) {
+        let Some(plane) = &self.plane else {
+            return pieces.push(polygon)
+        };
+        let mut split = Split::default();
+        split.add(plane, polygon);
+        for (node, polygons) in [
+            (&self.front, [split.front, split.coplanar_front]),
+            (&self.back, [split.back, split.coplanar_back]),
+        ] {
+            for polygon in polygons.into_iter().flatten() {
+                match node {
+                    Some(node) => node.partition(polygon, pieces),
+                    None => pieces.push(polygon),
+                }
+            }
+        }
+    }
+}
+
+/// A brush in world space, with a BSP tree of its volume.
+struct Solid {
+    csg_oper: CsgOper,
+    solid: bool,
+    polygons: Vec<Polygon>,
+    node: Node,
+    min: Vector,
+    max: Vector,
+}
+
+impl Solid {
+    fn new(brush: &T3dBrush, sources: &mut Vec<T3dPolygon>) -> Self {
+        let polygons: Vec<Polygon> = brush.world_polygons().into_iter().filter_map(|polygon| {
+            let vertices = polygon.vertices.iter().map(|v| v.map(f64::from)).collect();
+            sources.push(polygon);
+            Polygon::new(vertices, sources.len() - 1)
+        }).collect();
+        let (min, max) = bounds(&polygons);
+        // The area vectors of a closed surface add up to zero, while those of a sheet do not.
+        let (sum, total) = polygons.iter().fold(([0.0; 3], 0.0), |(sum, total), polygon| {
+            let area = area_vector(&polygon.vertices);
+            ([0, 1, 2].map(|i| sum[i] + area[i]), total + length(area))
+        });
+        let closed = !polygons.is_empty() && length(sum) < total * 1e-3;
+        Solid {
+            csg_oper: brush.csg_oper,
+            solid: closed && !brush.poly_flags.intersects(PolyFlags::NOT_SOLID | PolyFlags::SEMISOLID),
+            node: Node::new(polygons.clone()),
+            polygons,
+            min,
+            max,
+        }
+    }
+
+    fn overlaps(&self, min: Vector, max: Vector) -> bool {
+        (0..3).all(|i| self.min[i] <= max[i] + EPSILON && min[i] <= self.max[i] + EPSILON)
+    }
+
+    fn contains(&self, point: Vector) -> bool {
+        self.overlaps(point, point) && self.node.contains(point)
+    }
+
+    /// Whether a piece of a polygon lies on one of the polygons of this brush.
+    fn covers(&self, plane: &Plane, point: Vector) -> bool {
+        self.overlaps(point, point) && self.polygons.iter().any(|polygon| {
+            let same = dot(polygon.plane.normal, plane.normal) > 1.0 - EPSILON && (polygon.plane.w - plane.w).abs() < EPSILON;
+            let opposite = dot(polygon.plane.normal, plane.normal) < EPSILON - 1.0 && (polygon.plane.w + plane.w).abs() < EPSILON;
+            (same || opposite) && inside_polygon(polygon, point)
+        })
+    }
+}
+
+fn bounds(polygons: &[Polygon]) -> (Vector, Vector) {
+    polygons.iter().flat_map(|polygon| &polygon.vertices).fold(([f64::MAX; 3], [f64::MIN; 3]), |(min, max), v| {
+        ([0, 1, 2].map(|i| min[i].min(v[i])), [0, 1, 2].map(|i| max[i].max(v[i])))
+    })
+}
+
+/// Whether a point on the plane of a convex polygon is inside it.
+fn inside_polygon(polygon: &Polygon, point: Vector) -> bool {
+    let count = polygon.vertices.len();
+    (0..count).all(|i| {
+        let (a, b) = (polygon.vertices[i], polygon.vertices[(i + 1) % count]);
+        let edge = [0, 1, 2].map(|k| b[k] - a[k]);
+        let to_point = [0, 1, 2].map(|k| point[k] - a[k]);
+        let cross = [
+            edge[1] * to_point[2] - edge[2] * to_point[1],
+            edge[2] * to_point[0] - edge[0] * to_point[2],
+            edge[0] * to_point[1] - edge[1] * to_point[0],
+        ];
+        dot(cross, polygon.plane.normal) >= -EPSILON
+    })
+}
+
+fn centroid(polygon: &Polygon) -> Vector {
+    let sum = polygon.vertices.iter().fold([0.0; 3], |sum, v| [0, 1, 2].map(|i| sum[i] + v[i]));
+    sum.map(|c| c / polygon.vertices.len() as f64)
+}
+
+/// Whether a point is in empty space, which is the case if the last solid brush that contains it is subtractive.
+fn is_empty(solids: &[Solid], point: Vector) -> bool {
+    solids.iter().rev()
+        .find(|solid| solid.solid && solid.contains(point))
+        .is_some_and(|solid| solid.csg_oper == CsgOper::Subtract)
+}
+
+/// Whether an actor's brush is part of the level geometry. Movers and volumes, including subclasses such as
+/// `ElevatorMover` and `/Script/Engine.BlockingVolume`, are not.
+fn is_csg_class(object: &T3dObject) -> bool {
+    let class = object.class_name().unwrap_or_default();
+    let name = class.rsplit(['.', '/']).next().unwrap_or_default().to_ascii_lowercase();
+    !(name.ends_with("mover") || name.ends_with("volume"))
+}
+
+/// Whether an actor is the builder brush. UnrealEd names the model of the builder brush `Brush`, while the models of
+/// the brushes added to the level are named after them, e.g. `Model2`. The builder brush usually has no `CsgOper`
+/// either, which is skipped as `CSG_Active`, but it may be exported with the operation it was last used for.
+fn is_builder_brush(object: &T3dObject) -> bool {
+    let class = object.class_name().unwrap_or_default();
+    class.rsplit(['.', '/']).next().unwrap_or_default().eq_ignore_ascii_case("Brush") && object.children.iter()
+        .filter(|child| child.type_.eq_ignore_ascii_case("Brush"))
+        .any(|model| model.name().is_some_and(|name| name.eq_ignore_ascii_case("Brush")))
+}
+
+/// Collects the actors with a `Begin Brush` model in file order, along with whether they are part of the geometry.
+fn collect_brushes(object: &T3dObject, brushes: &mut Vec<(T3dBrush, bool)>) -> Result<(), T3dViewError> {
+    if object.children.iter().any(|child| child.type_.eq_ignore_ascii_case("Brush")) {
+        brushes.push((T3dBrush::from_object(object)?, is_csg_class(object) && !is_builder_brush(object)));
+    } else {
+        for child in &object.children {
+            collect_brushes(child, brushes)?;
+        }
+    }
+    Ok(())
+}
+
+/// Replays the brushes of the objects in file order, returning the polygons of the resulting level in world space.
+///
+/// Polygons keep the texture, flags and texture axes of the brush polygon they were cut from, and face into the
+/// empty space of the level. Polygons may be split into several convex pieces.
+pub fn csg_polygons(objects: &[T3dObject]) -> Result<Vec<T3dPolygon>, T3dViewError> {
+    let mut brushes = Vec::new();
+    for object in objects {
+        collect_brushes(object, &mut brushes)?;
+    }
+    let mut sources = Vec::new();
+    let solids: Vec<Solid> = brushes.iter()
+        .filter(|(brush, csg)| *csg && matches!(brush.csg_oper, CsgOper::Add | CsgOper::Subtract))
+        .map(|(brush, _)| Solid::new(brush, &mut sources))
+        .collect();
+    let mut polygons = Vec::new();
+    for (index, solid) in solids.iter().enumerate() {
+        if !solid.solid && solid.csg_oper != CsgOper::Add {
+            continue
+        }
+        for polygon in &solid.polygons {
+            let (min, max) = bounds(std::slice::from_ref(polygon));
+            let mut pieces = vec![polygon.clone()];
+            for (_, other) in solids.iter().enumerate().filter(|(i, other)| *i != index && other.solid && other.overlaps(min, max)) {
+                let mut split = Vec::new();
+                for piece in pieces {
+                    other.node.partition(piece, &mut split);
+                }
+                pieces = split;
+            }
+            for mut piece in pieces {
+                let center = centroid(&piece);
+                let normal = piece.plane.normal;
+                let front = is_empty(&solids, [0, 1, 2].map(|i| center[i] + normal[i] * PROBE));
+                let keep = if solid.solid {
+                    let back = is_empty(&solids, [0, 1, 2].map(|i| center[i] - normal[i] * PROBE));
+                    front != back && !solids[index + 1..].iter().any(|later| later.solid && later.covers(&piece.plane, center))
+                } else {
+                    front
+                };
+                if keep {
+                    if !front {
+                        piece.flip();
+                    }
+                    polygons.push(piece);
+                }
+            }
+        }
+    }
+    Ok(polygons.into_iter().map(|polygon| {
+        let source = &sources[polygon.source];
+        T3dPolygon {
+            normal: polygon.plane.normal.map(|c| c as f32),
+            vertices: polygon.vertices.iter().map(|v| v.map(|c| c as f32)).collect(),
+            ..source.clone()
+        }
+    }).collect())
+}
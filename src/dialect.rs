@@ -0,0 +1,190 @@
+//! The engine generations that T3D text is exported from, which differ in details of the format.
+
+use crate::ast::{T3dObject, T3dPropertyValue, T3dValue};
+use crate::flags::PolyFlagsLayout;
+
+/// An engine generation whose T3D text differs from the others.
+///
+/// A dialect can be detected from parsed objects with [`T3dDialect::detect`], or forced when parsing and writing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum T3dDialect {
+    /// Unreal Engine 1, e.g. Unreal and Unreal Tournament.
+    Ue1,
+    /// Unreal Engine 2, e.g. Unreal Tournament 2004.
+    Ue2,
+    /// Unreal Engine 2.5 as used by Red Orchestra and Darkest Hour.
+    Ue25,
+    /// Unreal Engine 3, e.g. UDK.
+    Ue3,
+    /// Unreal Engine 4.
+    Ue4,
+}
+
+/// Polygon header attributes that were added after UE1, e.g. `LightMapScale=32.000000`.
+const POLYGON_HEADERS_AFTER_UE1: &[&str] = &["LightMapScale", "SpecialLitChannel", "SurfaceTag", "ShadowMapScale", "LightingChannels"];
+
+/// Classes that only exist in UE2 and later.
+const UE2_CLASSES: &[&str] = &["StaticMeshActor", "TerrainInfo", "Emitter", "FluidSurfaceInfo", "Projector", "xPawn"];
+
+/// Packages of Red Orchestra and Darkest Hour.
+const UE25_PACKAGES: &[&str] = &["ROEngine", "ROGame", "ROInterface", "ROVehicles", "DH_Engine", "DH_Vehicles"];
+
+impl T3dDialect {
+    pub const ALL: [T3dDialect; 5] = [T3dDialect::Ue1, T3dDialect::Ue2, T3dDialect::Ue25, T3dDialect::Ue3, T3dDialect::Ue4];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            T3dDialect::Ue1 => "UE1",
+            T3dDialect::Ue2 => "UE2",
+            T3dDialect::Ue25 => "UE2.5",
+            T3dDialect::Ue3 => "UE3",
+            T3dDialect::Ue4 => "UE4",
+        }
+    }
+
+    /// Looks up a dialect by name, ignoring case, e.g. `ue2.5`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        T3dDialect::ALL.into_iter().find(|dialect| dialect.name().eq_ignore_ascii_case(name))
+    }
+
+    /// The meaning of the bits of polygon `Flags` and brush `PolyFlags`.
+    pub fn poly_flags_layout(&self) -> PolyFlagsLayout {
+        match self {
+            T3dDialect::Ue1 => PolyFlagsLayout::Ue1,
+            _ => PolyFlagsLayout::Ue2,
+        }
+    }
+
+    /// Whether string literals use backslash escapes. UE1 and UE2 write strings verbatim, so the backslashes in a
+    /// path such as `"C:\UnrealTournament\Maps"` are part of the string.
+    pub fn escapes_strings(&self) -> bool {
+        *self >= T3dDialect::Ue3
+    }
+
+    /// The number of rotation units per turn in rotators. UE4 exports rotators in degrees.
+    pub fn rotation_units_per_turn(&self) -> f32 {
+        match self {
+            T3dDialect::Ue4 => 360.0,
+            _ => 65536.0,
+        }
+    }
+
+    /// Guesses the dialect of parsed objects from their headers, class names and polygons, returning the newest
+    /// dialect that there is evidence of. Returns `None` if there are no actors or polygons to go by.
+    ///
+    /// * UE4: class paths such as `/Script/Engine.StaticMeshActor`, quoted asset paths and `CustomProperties`.
+    /// * UE3: `ObjName` and `Archetype` headers, and `Begin Level`.
+    /// * UE2.5: classes or references in the Red Orchestra and Darkest Hour packages.
+    /// * UE2: classes such as `StaticMeshActor`, properties such as `DrawScale3D`, and polygon headers such as
+    ///   `LightMapScale`.
+    pub fn detect(objects: &[T3dObject]) -> Option<Self> {
+        let mut evidence = Evidence::default();
+        for object in objects {
+            evidence.add(object);
+        }
+        evidence.dialect
+    }
+
+    /// Rewrites an object and its children into a form that the dialect can import. For UE1, this removes the
+    /// package from actor classes (`Class=Engine.Brush` becomes `Class=Brush`), and the polygon headers and
+    /// four-component `SurfaceColor` vectors of later engines.
+    pub(crate) fn adapt(&self, object: &mut T3dObject) {
+        if *self == T3dDialect::Ue1 {
+            if object.type_.eq_ignore_ascii_case("Actor") {
+                for (name, value) in &mut object.header {
+                    if let (true, T3dValue::Identifier(class)) = (name.eq_ignore_ascii_case("Class"), value) {
+                        if let Some((_, class_name)) = class.rsplit_once('.') {
+                            *class = String::from(class_name);
+                        }
+                    }
+                }
+            }
+            if object.type_.eq_ignore_ascii_case("Polygon") {
+                object.header.retain(|(name, _)| !POLYGON_HEADERS_AFTER_UE1.iter().any(|h| h.eq_ignore_ascii_case(name)));
+                object.vector_properties.retain(|(_, values)| values.len() == 3);
+            }
+        }
+        for child in &mut object.children {
+            self.adapt(child);
+        }
+    }
+}
+
+#[derive(Default)]
+struct Evidence {
+    dialect: Option<T3dDialect>,
+}
+
+impl Evidence {
+    fn found(&mut self, dialect: T3dDialect) {
+        self.dialect = self.dialect.max(Some(dialect));
+    }
+
+    fn add(&mut self, object: &T3dObject) {
+        if object.type_.eq_ignore_ascii_case("Actor") || object.type_.eq_ignore_ascii_case("Polygon") {
+            self.found(T3dDialect::Ue1);
+        }
+        if object.type_.eq_ignore_ascii_case("Level") {
+            self.found(T3dDialect::Ue3);
+        }
+        if !object.custom_properties.is_empty() {
+            self.found(T3dDialect::Ue4);
+        }
+        for (name, value) in &object.header {
+            if name.eq_ignore_ascii_case("ObjName") || name.eq_ignore_ascii_case("Archetype") {
+                self.found(T3dDialect::Ue3);
+            }
+            if name.eq_ignore_ascii_case("Class") {
+                self.class(value.to_string().as_str());
+            }
+            if object.type_.eq_ignore_ascii_case("Polygon") && POLYGON_HEADERS_AFTER_UE1.iter().any(|h| h.eq_ignore_ascii_case(name)) {
+                self.found(T3dDialect::Ue2);
+            }
+            self.value(value);
+        }
+        for (name, value) in &object.properties {
+            if name.eq_ignore_ascii_case("DrawScale3D") || name.eq_ignore_ascii_case("StaticMesh") {
+                self.found(T3dDialect::Ue2);
+            }
+            match value {
+                T3dPropertyValue::Value(value) => self.value(value),
+                T3dPropertyValue::Array(values) => values.iter().for_each(|(_, value)| self.value(value)),
+            }
+        }
+        for child in &object.children {
+            self.add(child);
+        }
+    }
+
+    fn class(&mut self, class: &str) {
+        if class.contains('/') {
+            self.found(T3dDialect::Ue4);
+        }
+        let (package, name) = class.rsplit_once('.').unwrap_or(("", class));
+        if UE2_CLASSES.iter().any(|c| c.eq_ignore_ascii_case(name)) || name.ends_with("Volume") {
+            self.found(T3dDialect::Ue2);
+        }
+        // Red Orchestra classes are prefixed with `RO`, e.g. `ROObjective`.
+        let ro_class = name.starts_with("RO") && name[2..].starts_with(|c: char| c.is_ascii_uppercase());
+        if ro_class || UE25_PACKAGES.iter().any(|p| p.eq_ignore_ascii_case(package)) {
+            self.found(T3dDialect::Ue25);
+        }
+    }
+
+    fn value(&mut self, value: &T3dValue) {
+        match value {
+            T3dValue::Reference(reference) => {
+                if reference.quoted || reference.type_.contains('/') || reference.path.starts_with("/Script/") {
+                    self.found(T3dDialect::Ue4);
+                }
+                let package = reference.path.split('.').next().unwrap_or_default();
+                if UE25_PACKAGES.iter().any(|p| p.eq_ignore_ascii_case(package)) {
+                    self.found(T3dDialect::Ue25);
+                }
+            },
+            T3dValue::Struct(values) | T3dValue::InlineStruct(values) => values.values().for_each(|value| self.value(value)),
+            T3dValue::Array(values) => values.iter().flatten().for_each(|value| self.value(value)),
+            _ => {},
+        }
+    }
+}
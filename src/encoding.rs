@@ -0,0 +1,162 @@
+//! Detection, decoding and encoding of the text encodings that T3D files are exported in.
+//!
+//! UnrealEd 2 and 3 export T3D files as UTF-16 LE, usually with a byte order mark, while older files are usually
+//! Windows-1252. Files without a byte order mark are read as UTF-16 if every other byte is NUL, as UTF-8 if they
+//! are valid UTF-8, and as Windows-1252 otherwise. Whether there was a byte order mark is part of the detected
+//! encoding, so that files are written back as they were read.
+
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// The text encoding of a T3D file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum T3dEncoding {
+    #[default]
+    Utf8,
+    /// UTF-8 with a byte order mark.
+    Utf8Bom,
+    /// UTF-16 little endian with a byte order mark.
+    Utf16Le,
+    /// UTF-16 big endian with a byte order mark.
+    Utf16Be,
+    /// UTF-16 little endian without a byte order mark.
+    Utf16LeNoBom,
+    /// UTF-16 big endian without a byte order mark.
+    Utf16BeNoBom,
+    Windows1252,
+}
+
+impl T3dEncoding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            T3dEncoding::Utf8 => "utf-8",
+            T3dEncoding::Utf8Bom => "utf-8-sig",
+            T3dEncoding::Utf16Le => "utf-16-le",
+            T3dEncoding::Utf16Be => "utf-16-be",
+            T3dEncoding::Utf16LeNoBom => "utf-16-le-nobom",
+            T3dEncoding::Utf16BeNoBom => "utf-16-be-nobom",
+            T3dEncoding::Windows1252 => "windows-1252",
+        }
+    }
+}
+
+impl Display for T3dEncoding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for T3dEncoding {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().replace('_', "-").as_str() {
+            "utf-8" | "utf8" => Ok(T3dEncoding::Utf8),
+            "utf-8-sig" => Ok(T3dEncoding::Utf8Bom),
+            "utf-16-le" | "utf-16le" | "utf-16" => Ok(T3dEncoding::Utf16Le),
+            "utf-16-be" | "utf-16be" => Ok(T3dEncoding::Utf16Be),
+            "utf-16-le-nobom" => Ok(T3dEncoding::Utf16LeNoBom),
+            "utf-16-be-nobom" => Ok(T3dEncoding::Utf16BeNoBom),
+            "windows-1252" | "cp1252" => Ok(T3dEncoding::Windows1252),
+            _ => Err(format!("unknown encoding: {}", name)),
+        }
+    }
+}
+
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+const UTF16_LE_BOM: &[u8] = &[0xFF, 0xFE];
+const UTF16_BE_BOM: &[u8] = &[0xFE, 0xFF];
+
+/// Characters of the Windows-1252 bytes 0x80 to 0x9F. The five bytes that are undefined map to the C1 control
+/// characters of the same value, as they do in browsers.
+const WINDOWS_1252: [char; 32] = [
+    '\u{20AC}', '\u{0081}', '\u{201A}', '\u{0192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02C6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\u{008D}', '\u{017D}', '\u{008F}',
+    '\u{0090}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02DC}', '\u{2122}', '\u{0161}', '\u{203A}', '\u{0153}', '\u{009D}', '\u{017E}', '\u{0178}',
+];
+
+/// The number of bytes that are looked at to detect UTF-16 without a byte order mark.
+const UTF16_SAMPLE_LEN: usize = 1024;
+
+/// Decodes UTF-16, replacing unpaired surrogates and a trailing odd byte of a truncated file with U+FFFD.
+fn decode_utf16(bytes: &[u8], from_bytes: fn([u8; 2]) -> u16) -> String {
+    let chunks = bytes.chunks_exact(2);
+    let truncated = !chunks.remainder().is_empty();
+    let units: Vec<u16> = chunks.map(|pair| from_bytes([pair[0], pair[1]])).collect();
+    let mut text = String::from_utf16_lossy(&units);
+    if truncated {
+        text.push(char::REPLACEMENT_CHARACTER);
+    }
+    text
+}
+
+/// Detects UTF-16 without a byte order mark from the NUL high bytes of ASCII characters, which make up most of a
+/// T3D file. Returns the endianness if at least half of the characters at the start of the file have a NUL high
+/// byte and a non-NUL low byte.
+fn detect_utf16(bytes: &[u8]) -> Option<T3dEncoding> {
+    let sample = &bytes[..bytes.len().min(UTF16_SAMPLE_LEN)];
+    let pairs = sample.len() / 2;
+    if pairs == 0 {
+        return None
+    }
+    let count = |high: usize| sample.chunks_exact(2).filter(|pair| pair[high] == 0 && pair[1 - high] != 0).count();
+    if count(1) * 2 >= pairs {
+        Some(T3dEncoding::Utf16LeNoBom)
+    } else if count(0) * 2 >= pairs {
+        Some(T3dEncoding::Utf16BeNoBom)
+    } else {
+        None
+    }
+}
+
+fn decode_windows_1252(bytes: &[u8]) -> String {
+    bytes.iter().map(|&byte| match byte {
+        0x80..=0x9F => WINDOWS_1252[(byte - 0x80) as usize],
+        _ => byte as char,
+    }).collect()
+}
+
+fn encode_windows_1252(text: &str) -> Vec<u8> {
+    text.chars().map(|c| match c as u32 {
+        code @ (0x00..=0x7F | 0xA0..=0xFF) => code as u8,
+        _ => WINDOWS_1252.iter().position(|&d| d == c).map_or(b'?', |index| 0x80 + index as u8),
+    }).collect()
+}
+
+/// Detects the encoding of a file from its byte order mark, or from its contents if it has none, and decodes it.
+pub fn decode(bytes: &[u8]) -> (String, T3dEncoding) {
+    if let Some(rest) = bytes.strip_prefix(UTF8_BOM) {
+        (String::from_utf8_lossy(rest).into_owned(), T3dEncoding::Utf8Bom)
+    } else if let Some(rest) = bytes.strip_prefix(UTF16_LE_BOM) {
+        (decode_utf16(rest, u16::from_le_bytes), T3dEncoding::Utf16Le)
+    } else if let Some(rest) = bytes.strip_prefix(UTF16_BE_BOM) {
+        (decode_utf16(rest, u16::from_be_bytes), T3dEncoding::Utf16Be)
+    } else if let Some(encoding) = detect_utf16(bytes) {
+        let from_bytes = if encoding == T3dEncoding::Utf16LeNoBom { u16::from_le_bytes } else { u16::from_be_bytes };
+        (decode_utf16(bytes, from_bytes), encoding)
+    } else {
+        match std::str::from_utf8(bytes) {
+            Ok(text) => (String::from(text), T3dEncoding::Utf8),
+            Err(_) => (decode_windows_1252(bytes), T3dEncoding::Windows1252),
+        }
+    }
+}
+
+/// Encodes text, including the byte order mark of the encoding if it has one. Characters that cannot be represented in
+/// Windows-1252 are replaced with `?`.
+pub fn encode(text: &str, encoding: T3dEncoding) -> Vec<u8> {
+    match encoding {
+        T3dEncoding::Utf8 => text.as_bytes().to_vec(),
+        T3dEncoding::Utf8Bom => [UTF8_BOM, text.as_bytes()].concat(),
+        T3dEncoding::Utf16Le => {
+            UTF16_LE_BOM.iter().copied().chain(text.encode_utf16().flat_map(u16::to_le_bytes)).collect()
+        },
+        T3dEncoding::Utf16Be => {
+            UTF16_BE_BOM.iter().copied().chain(text.encode_utf16().flat_map(u16::to_be_bytes)).collect()
+        },
+        T3dEncoding::Utf16LeNoBom => text.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+        T3dEncoding::Utf16BeNoBom => text.encode_utf16().flat_map(u16::to_be_bytes).collect(),
+        T3dEncoding::Windows1252 => encode_windows_1252(text),
+    }
+}
@@ -0,0 +1,198 @@
+//! Export of brush geometry to Wavefront OBJ, for previewing maps outside of the editor.
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter, Write};
+use std::path::Path;
+use crate::ast::{T3dObject, T3dViewError};
+use crate::brush::T3dBrush;
+use crate::csg::csg_polygons;
+use crate::polygon::{T3dPolygon, T3dVector};
+
+/// Options that control how geometry is exported.
+#[derive(Debug, Clone)]
+pub struct T3dExportOptions {
+    /// The width and height in texels of textures that are not in `texture_sizes`, used to scale texture coordinates.
+    pub texture_size: (u32, u32),
+    /// The sizes of individual textures, by the name in the polygon `Texture=` header.
+    pub texture_sizes: HashMap<String, (u32, u32)>,
+    /// Export the level geometry built from the brushes with [`csg_polygons`], as a single `World` group, instead of
+    /// the polygons of every brush.
+    pub csg: bool,
+}
+
+impl Default for T3dExportOptions {
+    fn default() -> Self {
+        T3dExportOptions { texture_size: (256, 256), texture_sizes: HashMap::new(), csg: false }
+    }
+}
+
+impl T3dExportOptions {
+    pub(crate) fn texture_size(&self, texture: Option<&str>) -> (f32, f32) {
+        let (width, height) = texture.and_then(|texture| self.texture_sizes.get(texture)).unwrap_or(&self.texture_size);
+        (*width as f32, *height as f32)
+    }
+}
+
+/// An error that occurred while exporting geometry.
+#[derive(Debug)]
+pub enum T3dExportError {
+    Io(std::io::Error),
+    /// A brush or polygon could not be read.
+    View(T3dViewError),
+}
+
+impl Display for T3dExportError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            T3dExportError::Io(err) => write!(f, "{}", err),
+            T3dExportError::View(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for T3dExportError {}
+
+impl From<std::io::Error> for T3dExportError {
+    fn from(err: std::io::Error) -> Self {
+        T3dExportError::Io(err)
+    }
+}
+
+impl From<T3dViewError> for T3dExportError {
+    fn from(err: T3dViewError) -> Self {
+        T3dExportError::View(err)
+    }
+}
+
+/// A polygon along with the name of the actor it belongs to.
+pub(crate) struct ExportPolygon {
+    pub group: String,
+    pub polygon: T3dPolygon,
+}
+
+fn collect(object: &T3dObject, group: &str, polygons: &mut Vec<ExportPolygon>) -> Result<(), T3dViewError> {
+    let name = object.name();
+    let group = name.as_deref().unwrap_or(group);
+    if object.type_.eq_ignore_ascii_case("Polygon") {
+        polygons.push(ExportPolygon { group: String::from(group), polygon: T3dPolygon::from_object(object)? });
+    } else if object.children.iter().any(|child| child.type_.eq_ignore_ascii_case("Brush")) {
+        let brush = T3dBrush::from_object(object)?;
+        polygons.extend(brush.world_polygons().into_iter().map(|polygon| {
+            ExportPolygon { group: String::from(group), polygon }
+        }));
+    } else {
+        for child in &object.children {
+            collect(child, group, polygons)?;
+        }
+    }
+    Ok(())
+}
+
+/// Collects every polygon in world space. Polygons of actors with a brush model are transformed by the brush, while
+/// any other polygons are left as they are.
+pub(crate) fn export_polygons(objects: &[T3dObject], options: &T3dExportOptions) -> Result<Vec<ExportPolygon>, T3dViewError> {
+    if options.csg {
+        return Ok(csg_polygons(objects)?.into_iter().map(|polygon| {
+            ExportPolygon { group: String::from("World"), polygon }
+        }).collect())
+    }
+    let mut polygons = Vec::new();
+    for object in objects {
+        collect(object, object.type_.as_str(), &mut polygons)?;
+    }
+    Ok(polygons)
+}
+
+/// Computes the texture coordinates of a vertex the way Unreal does, as `((vertex - Origin) . TextureU + PanU)`
+/// divided by the texture width, and likewise for V.
+pub(crate) fn texture_coordinates(polygon: &T3dPolygon, vertex: T3dVector, size: (f32, f32)) -> [f32; 2] {
+    let dot = |axis: T3dVector| (0..3).map(|i| (vertex[i] - polygon.origin[i]) * axis[i]).sum::<f32>();
+    [(dot(polygon.texture_u) + polygon.pan_u as f32) / size.0, (dot(polygon.texture_v) + polygon.pan_v as f32) / size.1]
+}
+
+/// The name of the material of a polygon, from its `Texture=` header.
+pub(crate) fn material_name(polygon: &T3dPolygon) -> &str {
+    polygon.texture.as_deref().unwrap_or("None")
+}
+
+/// Converts a vector from Unreal's left-handed coordinates to OBJ's right-handed ones by negating Y.
+fn obj_vector(v: T3dVector) -> T3dVector {
+    [v[0], -v[1], v[2]]
+}
+
+/// Writes an OBJ statement such as `v 1 2 3`, writing negative zeros as `0`.
+fn statement(obj: &mut String, keyword: &str, values: &[f32]) {
+    obj.push_str(keyword);
+    for value in values {
+        write!(obj, " {}", value + 0.0).unwrap();
+    }
+    obj.push('\n');
+}
+
+/// Writes the polygons of the objects as Wavefront OBJ text, along with the text of the material library that it
+/// refers to as `mtllib`.
+///
+/// Faces are grouped by actor with `g` and by texture with `usemtl`. Polygons are triangulated as fans, which is
+/// correct for the convex polygons that brushes are made of. Y is negated to convert to a right-handed coordinate
+/// system, which also reverses the winding of every face. Texture V is negated, since OBJ texture coordinates
+/// start at the bottom of the texture.
+pub fn write_obj(objects: &[T3dObject], mtllib: &str, options: &T3dExportOptions) -> Result<(String, String), T3dViewError> {
+    let mut polygons = export_polygons(objects, options)?;
+    // The polygons of each actor are contiguous, so sorting them by texture starts every material once per actor.
+    for actor in polygons.chunk_by_mut(|a, b| a.group == b.group) {
+        actor.sort_by(|a, b| material_name(&a.polygon).cmp(material_name(&b.polygon)));
+    }
+    let mut obj = String::new();
+    writeln!(obj, "mtllib {}", mtllib).unwrap();
+    let mut materials = Vec::new();
+    let mut current: Option<(&str, &str)> = None;
+    let mut vertex_count = 0;
+    for (normal_index, ExportPolygon { group, polygon }) in polygons.iter().enumerate() {
+        let material = material_name(polygon);
+        if current.map(|(g, _)| g) != Some(group.as_str()) {
+            writeln!(obj, "g {}", group).unwrap();
+            current = None;
+        }
+        if current.map(|(_, m)| m) != Some(material) {
+            writeln!(obj, "usemtl {}", material).unwrap();
+            if !materials.contains(&material) {
+                materials.push(material);
+            }
+        }
+        current = Some((group.as_str(), material));
+        let size = options.texture_size(polygon.texture.as_deref());
+        for &vertex in &polygon.vertices {
+            let [u, v] = texture_coordinates(polygon, vertex, size);
+            statement(&mut obj, "v", &obj_vector(vertex));
+            statement(&mut obj, "vt", &[u, -v]);
+        }
+        statement(&mut obj, "vn", &obj_vector(polygon.normal));
+        let index = |i: usize| format!("{}/{}/{}", vertex_count + i + 1, vertex_count + i + 1, normal_index + 1);
+        for i in 1..polygon.vertices.len() - 1 {
+            writeln!(obj, "f {} {} {}", index(0), index(i + 1), index(i)).unwrap();
+        }
+        vertex_count += polygon.vertices.len();
+    }
+    let mut mtl = String::new();
+    for material in materials {
+        writeln!(mtl, "newmtl {}", material).unwrap();
+        writeln!(mtl, "Kd 0.8 0.8 0.8").unwrap();
+    }
+    Ok((obj, mtl))
+}
+
+/// Exports the polygons of the objects to an OBJ file, along with a material library next to it with the extension
+/// `.mtl`.
+pub fn export_obj<P: AsRef<Path>>(objects: &[T3dObject], path: P) -> Result<(), T3dExportError> {
+    export_obj_with_options(objects, path, &T3dExportOptions::default())
+}
+
+pub fn export_obj_with_options<P: AsRef<Path>>(objects: &[T3dObject], path: P, options: &T3dExportOptions) -> Result<(), T3dExportError> {
+    let path = path.as_ref();
+    let mtl_path = path.with_extension("mtl");
+    let mtllib = mtl_path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let (obj, mtl) = write_obj(objects, mtllib.as_str(), options)?;
+    std::fs::write(path, obj)?;
+    std::fs::write(mtl_path, mtl)?;
+    Ok(())
+}
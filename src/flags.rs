@@ -0,0 +1,212 @@
+//! Polygon flags, from the `Flags` header of polygons and the `PolyFlags` property of brushes.
+
+use std::fmt::{Display, Formatter};
+use std::ops::{BitAnd, BitOr, BitOrAssign, Not};
+
+/// The meaning of the polygon flag bits, which differs between engine generations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PolyFlagsLayout {
+    /// Unreal Engine 1, e.g. Unreal and Unreal Tournament.
+    #[default]
+    Ue1,
+    /// Unreal Engine 2, e.g. Unreal Tournament 2004, where most rendering flags moved to materials.
+    Ue2,
+}
+
+/// The names of the UE1 flags. Where several names share a bit, the first one is used when decoding.
+const UE1_FLAGS: &[(&str, u32)] = &[
+    ("Invisible", 0x0000_0001),
+    ("Masked", 0x0000_0002),
+    ("Translucent", 0x0000_0004),
+    ("NotSolid", 0x0000_0008),
+    ("Environment", 0x0000_0010),
+    ("ForceViewZone", 0x0000_0010),
+    ("Semisolid", 0x0000_0020),
+    ("Modulated", 0x0000_0040),
+    ("FakeBackdrop", 0x0000_0080),
+    ("TwoSided", 0x0000_0100),
+    ("AutoUPan", 0x0000_0200),
+    ("AutoVPan", 0x0000_0400),
+    ("NoSmooth", 0x0000_0800),
+    ("BigWavy", 0x0000_1000),
+    ("SpecialPoly", 0x0000_1000),
+    ("SmallWavy", 0x0000_2000),
+    ("Flat", 0x0000_4000),
+    ("LowShadowDetail", 0x0000_8000),
+    ("NoMerge", 0x0001_0000),
+    ("CloudWavy", 0x0002_0000),
+    ("DirtyShadows", 0x0004_0000),
+    ("BrightCorners", 0x0008_0000),
+    ("SpecialLit", 0x0010_0000),
+    ("Gouraud", 0x0020_0000),
+    ("NoBoundRejection", 0x0020_0000),
+    ("Unlit", 0x0040_0000),
+    ("HighShadowDetail", 0x0080_0000),
+    ("Memorized", 0x0100_0000),
+    ("RenderHint", 0x0100_0000),
+    ("Selected", 0x0200_0000),
+    ("Portal", 0x0400_0000),
+    ("Mirrored", 0x0800_0000),
+    ("Highlighted", 0x1000_0000),
+    ("FlatShaded", 0x4000_0000),
+    ("EdProcessed", 0x4000_0000),
+    ("RenderFog", 0x4000_0000),
+    ("EdCut", 0x8000_0000),
+    ("Occlude", 0x8000_0000),
+];
+
+const UE2_FLAGS: &[(&str, u32)] = &[
+    ("Invisible", 0x0000_0001),
+    ("NotSolid", 0x0000_0008),
+    ("Semisolid", 0x0000_0020),
+    ("GeomMarked", 0x0000_0040),
+    ("TwoSided", 0x0000_0100),
+    ("SpecialLit", 0x0010_0000),
+    ("Unlit", 0x0040_0000),
+    ("Memorized", 0x0100_0000),
+    ("Selected", 0x0200_0000),
+    ("Portal", 0x0400_0000),
+    ("Mirrored", 0x0800_0000),
+    ("EdProcessed", 0x4000_0000),
+    ("EdCut", 0x8000_0000),
+];
+
+impl PolyFlagsLayout {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PolyFlagsLayout::Ue1 => "ue1",
+            PolyFlagsLayout::Ue2 => "ue2",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [PolyFlagsLayout::Ue1, PolyFlagsLayout::Ue2].into_iter().find(|layout| layout.name().eq_ignore_ascii_case(name))
+    }
+
+    fn flags(&self) -> &'static [(&'static str, u32)] {
+        match self {
+            PolyFlagsLayout::Ue1 => UE1_FLAGS,
+            PolyFlagsLayout::Ue2 => UE2_FLAGS,
+        }
+    }
+}
+
+/// A set of polygon flags. The bits are kept as they are, so flags that are unknown to a layout survive a
+/// round-trip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct PolyFlags(pub u32);
+
+impl PolyFlags {
+    pub const INVISIBLE: PolyFlags = PolyFlags(0x0000_0001);
+    pub const NOT_SOLID: PolyFlags = PolyFlags(0x0000_0008);
+    pub const SEMISOLID: PolyFlags = PolyFlags(0x0000_0020);
+    pub const TWO_SIDED: PolyFlags = PolyFlags(0x0000_0100);
+    pub const SPECIAL_LIT: PolyFlags = PolyFlags(0x0010_0000);
+    pub const UNLIT: PolyFlags = PolyFlags(0x0040_0000);
+    pub const PORTAL: PolyFlags = PolyFlags(0x0400_0000);
+    pub const MIRRORED: PolyFlags = PolyFlags(0x0800_0000);
+    /// UE1 only.
+    pub const MASKED: PolyFlags = PolyFlags(0x0000_0002);
+    /// UE1 only.
+    pub const TRANSLUCENT: PolyFlags = PolyFlags(0x0000_0004);
+    /// UE1 only.
+    pub const MODULATED: PolyFlags = PolyFlags(0x0000_0040);
+
+    pub fn bits(&self) -> u32 {
+        self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn contains(&self, other: PolyFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn intersects(&self, other: PolyFlags) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    pub fn insert(&mut self, other: PolyFlags) {
+        self.0 |= other.0;
+    }
+
+    pub fn remove(&mut self, other: PolyFlags) {
+        self.0 &= !other.0;
+    }
+
+    /// Looks up a flag by name, ignoring case, e.g. `TwoSided`. Names may also be hexadecimal bits such as
+    /// `0x00000200`, as returned by [`PolyFlags::names`] for unknown bits.
+    pub fn from_name(name: &str, layout: PolyFlagsLayout) -> Option<Self> {
+        if let Some(hex) = name.strip_prefix("0x").or_else(|| name.strip_prefix("0X")) {
+            return u32::from_str_radix(hex, 16).ok().map(PolyFlags)
+        }
+        layout.flags().iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, bit)| PolyFlags(*bit))
+    }
+
+    pub fn from_names<I, S>(names: I, layout: PolyFlagsLayout) -> Result<Self, String>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        names.into_iter().try_fold(PolyFlags::default(), |flags, name| {
+            let name = name.as_ref();
+            PolyFlags::from_name(name, layout).map(|flag| flags | flag).ok_or_else(|| format!("unknown flag: {}", name))
+        })
+    }
+
+    /// Returns the name of every set flag, in bit order. Bits that have no name in the layout are returned in
+    /// hexadecimal, e.g. `0x20000000`.
+    pub fn names(&self, layout: PolyFlagsLayout) -> Vec<String> {
+        (0..32).map(|i| 1u32 << i).filter(|bit| self.0 & bit != 0).map(|bit| {
+            match layout.flags().iter().find(|(_, b)| *b == bit) {
+                Some((name, _)) => String::from(*name),
+                None => format!("0x{:08X}", bit),
+            }
+        }).collect()
+    }
+}
+
+impl BitOr for PolyFlags {
+    type Output = PolyFlags;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        PolyFlags(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for PolyFlags {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl BitAnd for PolyFlags {
+    type Output = PolyFlags;
+
+    fn bitand(self, rhs: Self) -> Self::Output {
+        PolyFlags(self.0 & rhs.0)
+    }
+}
+
+impl Not for PolyFlags {
+    type Output = PolyFlags;
+
+    fn not(self) -> Self::Output {
+        PolyFlags(!self.0)
+    }
+}
+
+impl From<u32> for PolyFlags {
+    fn from(bits: u32) -> Self {
+        PolyFlags(bits)
+    }
+}
+
+/// Formats the flags as the integer that is written to T3D files, which is signed in files exported by UnrealEd.
+impl Display for PolyFlags {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0 as i32)
+    }
+}
@@ -0,0 +1,300 @@
+//! Export of actors and brush geometry to binary glTF 2.0 (`.glb`).
+//!
+//! Every actor becomes a node with the transform from its `Location`, `Rotation`, `DrawScale` and `DrawScale3D`
+//! properties, or from the `RelativeLocation`, `RelativeRotation` and `RelativeScale3D` of its root component in UE4,
+//! and its class, name and properties (tagged as in [`crate::json`]) as `extras`. Brushes get a mesh
+//! with a primitive for each texture, and every distinct texture gets a placeholder material of the same name.
+//!
+//! Coordinates are converted from Unreal's left-handed, Z-up coordinates to glTF's right-handed, Y-up ones by
+//! swapping Y and Z. Distances are left in Unreal units.
+
+use std::path::Path;
+use serde_json::{json, Value};
+use crate::ast::{T3dObject, T3dPropertyValue, T3dViewError};
+use crate::brush::{component_transform, components, number, rotation_matrix, struct_property, transform, vector_property, Matrix, T3dBrush};
+use crate::dialect::T3dDialect;
+use crate::export::{material_name, texture_coordinates, T3dExportError, T3dExportOptions};
+use crate::polygon::T3dVector;
+
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+
+/// Converts a vector from Unreal's coordinates to glTF's by swapping Y and Z.
+fn gltf_vector(v: [f64; 3]) -> [f32; 3] {
+    [v[0] as f32, v[2] as f32, v[1] as f32]
+}
+
+fn transpose(m: &Matrix) -> Matrix {
+    [0, 1, 2].map(|i| [0, 1, 2].map(|j| m[j][i]))
+}
+
+/// The glTF rotation quaternion `[x, y, z, w]` of an Unreal rotation matrix.
+fn quaternion(rotation: &Matrix) -> [f32; 4] {
+    // The column-vector matrix in glTF coordinates, with Y and Z swapped.
+    let axis = [0, 2, 1];
+    let m = [0, 1, 2].map(|i| [0, 1, 2].map(|j| rotation[axis[j]][axis[i]]));
+    let trace = m[0][0] + m[1][1] + m[2][2];
+    let [x, y, z, w] = if trace > 0.0 {
+        let s = 0.5 / (trace + 1.0).sqrt();
+        [(m[2][1] - m[1][2]) * s, (m[0][2] - m[2][0]) * s, (m[1][0] - m[0][1]) * s, 0.25 / s]
+    } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+        let s = 2.0 * (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt();
+        [0.25 * s, (m[0][1] + m[1][0]) / s, (m[0][2] + m[2][0]) / s, (m[2][1] - m[1][2]) / s]
+    } else if m[1][1] > m[2][2] {
+        let s = 2.0 * (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt();
+        [(m[0][1] + m[1][0]) / s, 0.25 * s, (m[1][2] + m[2][1]) / s, (m[0][2] - m[2][0]) / s]
+    } else {
+        let s = 2.0 * (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt();
+        [(m[0][2] + m[2][0]) / s, (m[1][2] + m[2][1]) / s, 0.25 * s, (m[1][0] - m[0][1]) / s]
+    };
+    [x as f32, y as f32, z as f32, w as f32]
+}
+
+/// The scale of an actor, from `DrawScale` multiplied by `DrawScale3D`.
+fn draw_scale(object: &T3dObject) -> Result<T3dVector, T3dViewError> {
+    let span = object.property_span("DrawScale", None);
+    let scale = match object.property("DrawScale") {
+        None => 1.0,
+        Some(T3dPropertyValue::Value(value)) => number(value, "DrawScale", span)?,
+        Some(_) => return Err(T3dViewError { message: String::from("DrawScale must be a number"), span }),
+    };
+    let scale_3d = match struct_property(object, "DrawScale3D")? {
+        Some((value, span)) => components(value, ["X", "Y", "Z"], 1.0, span)?,
+        None => [1.0; 3],
+    };
+    Ok(scale_3d.map(|c| c * scale))
+}
+
+/// The location, rotation and scale of an actor. UE4 actors are placed by their root component.
+fn actor_transform(actor: &T3dObject, dialect: Option<T3dDialect>) -> Result<(T3dVector, T3dVector, T3dVector), T3dViewError> {
+    if dialect == Some(T3dDialect::Ue4) {
+        if let Some(transform) = component_transform(actor)? {
+            return Ok(transform)
+        }
+    }
+    let location = vector_property(actor, "Location", ["X", "Y", "Z"])?;
+    let rotation = vector_property(actor, "Rotation", ["Pitch", "Yaw", "Roll"])?;
+    Ok((location, rotation, draw_scale(actor)?))
+}
+
+/// The binary buffer along with the buffer views and accessors that refer to it.
+#[derive(Default)]
+struct Buffer {
+    bin: Vec<u8>,
+    buffer_views: Vec<Value>,
+    accessors: Vec<Value>,
+}
+
+impl Buffer {
+    /// Appends a buffer view and an accessor for it, returning the index of the accessor.
+    fn add(&mut self, bytes: Vec<u8>, count: usize, type_: &str, component_type: u32, target: u32, extra: Value) -> usize {
+        let mut accessor = json!({
+            "bufferView": self.buffer_views.len(),
+            "componentType": component_type,
+            "count": count,
+            "type": type_,
+        });
+        if let (Some(accessor), Value::Object(extra)) = (accessor.as_object_mut(), extra) {
+            accessor.extend(extra);
+        }
+        self.buffer_views.push(json!({
+            "buffer": 0,
+            "byteOffset": self.bin.len(),
+            "byteLength": bytes.len(),
+            "target": target,
+        }));
+        self.bin.extend(bytes);
+        self.accessors.push(accessor);
+        self.accessors.len() - 1
+    }
+
+    fn add_floats<const N: usize>(&mut self, values: &[[f32; N]], type_: &str, extra: Value) -> usize {
+        let bytes = values.iter().flatten().flat_map(|value| value.to_le_bytes()).collect();
+        self.add(bytes, values.len(), type_, FLOAT, ARRAY_BUFFER, extra)
+    }
+}
+
+/// The vertices of the polygons of a brush that use one material, in the local space of the actor's node.
+#[derive(Default)]
+struct Primitive {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    texture_coordinates: Vec<[f32; 2]>,
+    indices: Vec<u32>,
+}
+
+/// Builds the primitives of a brush, grouped by material. The world polygons are transformed back into the local
+/// space of the node so that applying the node transform places them exactly where UnrealEd does.
+fn brush_primitives(
+    brush: &T3dBrush,
+    rotation: &Matrix,
+    scale: T3dVector,
+    materials: &mut Vec<String>,
+    options: &T3dExportOptions,
+) -> Vec<(usize, Primitive)> {
+    let inverse_rotation = transpose(rotation);
+    let to_local = |vertex: T3dVector| {
+        let offset = [0, 1, 2].map(|i| vertex[i] - brush.location[i]);
+        let local = transform(offset, &inverse_rotation);
+        [0, 1, 2].map(|i| local[i] / f64::from(scale[i]))
+    };
+    let mut primitives: Vec<(usize, Primitive)> = Vec::new();
+    for polygon in brush.world_polygons() {
+        let name = material_name(&polygon);
+        let material = materials.iter().position(|m| m == name).unwrap_or_else(|| {
+            materials.push(String::from(name));
+            materials.len() - 1
+        });
+        let index = match primitives.iter().position(|(m, _)| *m == material) {
+            Some(index) => index,
+            None => {
+                primitives.push((material, Primitive::default()));
+                primitives.len() - 1
+            },
+        };
+        let primitive = &mut primitives[index].1;
+        let normal = transform(polygon.normal, &inverse_rotation);
+        let normal = [0, 1, 2].map(|i| normal[i] * f64::from(scale[i]));
+        let length = normal.iter().map(|c| c * c).sum::<f64>().sqrt();
+        let normal = gltf_vector(if length > 0.0 { normal.map(|c| c / length) } else { normal });
+        let size = options.texture_size(polygon.texture.as_deref());
+        let first = primitive.positions.len() as u32;
+        for &vertex in &polygon.vertices {
+            primitive.positions.push(gltf_vector(to_local(vertex)));
+            primitive.normals.push(normal);
+            primitive.texture_coordinates.push(texture_coordinates(&polygon, vertex, size));
+        }
+        // Swapping Y and Z mirrors the geometry, which reverses the winding.
+        for i in 1..polygon.vertices.len() as u32 - 1 {
+            primitive.indices.extend([first, first + i + 1, first + i]);
+        }
+    }
+    primitives
+}
+
+fn collect_actors<'a>(object: &'a T3dObject, actors: &mut Vec<&'a T3dObject>) {
+    if object.type_.eq_ignore_ascii_case("Actor") {
+        actors.push(object);
+    } else {
+        for child in &object.children {
+            collect_actors(child, actors);
+        }
+    }
+}
+
+/// Writes the actors of the objects as a binary glTF file.
+pub fn write_glb(objects: &[T3dObject], options: &T3dExportOptions) -> Result<Vec<u8>, T3dViewError> {
+    let mut actors = Vec::new();
+    for object in objects {
+        collect_actors(object, &mut actors);
+    }
+    let mut buffer = Buffer::default();
+    let mut nodes = Vec::new();
+    let mut meshes = Vec::new();
+    let mut materials = Vec::new();
+    let dialect = T3dDialect::detect(objects);
+    for actor in actors {
+        let (location, rotation, mut scale) = actor_transform(actor, dialect)?;
+        let rotation = rotation_matrix(rotation);
+        let brush = match (actor.children.iter().any(|child| child.type_.eq_ignore_ascii_case("Brush")), dialect) {
+            (false, _) => None,
+            (true, Some(dialect)) => Some(T3dBrush::from_object_with_dialect(actor, dialect)?),
+            (true, None) => Some(T3dBrush::from_object(actor)?),
+        };
+        // The brush vertices are divided by the scale of the node, so a brush with a zero scale has it baked in.
+        if brush.is_some() && scale.iter().any(|c| !c.is_normal()) {
+            scale = [1.0; 3];
+        }
+        let mut node = json!({
+            "translation": gltf_vector(location.map(f64::from)),
+            "rotation": quaternion(&rotation),
+            "scale": [scale[0], scale[2], scale[1]],
+            "extras": {
+                "class": actor.class_name(),
+                "name": actor.name(),
+                "properties": serde_json::to_value(&actor.properties).unwrap_or_default(),
+            },
+        });
+        if let Some(name) = actor.name() {
+            node["name"] = json!(name);
+        }
+        if let Some(brush) = brush {
+            let primitives = brush_primitives(&brush, &rotation, scale, &mut materials, options);
+            if !primitives.is_empty() {
+                let primitives: Vec<Value> = primitives.into_iter().map(|(material, primitive)| {
+                    let (min, max) = primitive.positions.iter().fold(([f32::MAX; 3], [f32::MIN; 3]), |(min, max), p| {
+                        ([0, 1, 2].map(|i| min[i].min(p[i])), [0, 1, 2].map(|i| max[i].max(p[i])))
+                    });
+                    let position = buffer.add_floats(&primitive.positions, "VEC3", json!({"min": min, "max": max}));
+                    let normal = buffer.add_floats(&primitive.normals, "VEC3", Value::Null);
+                    let texcoord = buffer.add_floats(&primitive.texture_coordinates, "VEC2", Value::Null);
+                    let bytes = primitive.indices.iter().flat_map(|index| index.to_le_bytes()).collect();
+                    let indices = buffer.add(bytes, primitive.indices.len(), "SCALAR", UNSIGNED_INT, ELEMENT_ARRAY_BUFFER, Value::Null);
+                    json!({
+                        "attributes": {"POSITION": position, "NORMAL": normal, "TEXCOORD_0": texcoord},
+                        "indices": indices,
+                        "material": material,
+                    })
+                }).collect();
+                node["mesh"] = json!(meshes.len());
+                meshes.push(json!({"name": actor.name(), "primitives": primitives}));
+            }
+        }
+        nodes.push(node);
+    }
+    let mut gltf = json!({
+        "asset": {"version": "2.0", "generator": concat!("t3d ", env!("CARGO_PKG_VERSION"))},
+    });
+    // glTF does not allow empty arrays, so they are left out.
+    if !nodes.is_empty() {
+        gltf["scene"] = json!(0);
+        gltf["scenes"] = json!([{"nodes": (0..nodes.len()).collect::<Vec<usize>>()}]);
+        gltf["nodes"] = json!(nodes);
+    }
+    if !materials.is_empty() {
+        gltf["materials"] = materials.iter().map(|name| json!({
+            "name": name,
+            "pbrMetallicRoughness": {"baseColorFactor": [0.8, 0.8, 0.8, 1.0], "metallicFactor": 0.0},
+        })).collect();
+    }
+    if !meshes.is_empty() {
+        gltf["meshes"] = json!(meshes);
+        gltf["accessors"] = json!(buffer.accessors);
+        gltf["bufferViews"] = json!(buffer.buffer_views);
+        gltf["buffers"] = json!([{"byteLength": buffer.bin.len()}]);
+    }
+    Ok(glb(gltf.to_string().into_bytes(), buffer.bin))
+}
+
+/// Packs the JSON and binary chunks into a GLB container, padding each chunk to four bytes.
+fn glb(mut json: Vec<u8>, mut bin: Vec<u8>) -> Vec<u8> {
+    json.resize(json.len().next_multiple_of(4), b' ');
+    bin.resize(bin.len().next_multiple_of(4), 0);
+    let bin_chunk_length = if bin.is_empty() { 0 } else { 8 + bin.len() };
+    let length = 12 + 8 + json.len() + bin_chunk_length;
+    let mut glb = Vec::with_capacity(length);
+    glb.extend(b"glTF");
+    glb.extend(2u32.to_le_bytes());
+    glb.extend((length as u32).to_le_bytes());
+    glb.extend((json.len() as u32).to_le_bytes());
+    glb.extend(b"JSON");
+    glb.extend(json);
+    if !bin.is_empty() {
+        glb.extend((bin.len() as u32).to_le_bytes());
+        glb.extend(b"BIN\0");
+        glb.extend(bin);
+    }
+    glb
+}
+
+/// Exports the actors of the objects to a binary glTF file.
+pub fn export_glb<P: AsRef<Path>>(objects: &[T3dObject], path: P) -> Result<(), T3dExportError> {
+    export_glb_with_options(objects, path, &T3dExportOptions::default())
+}
+
+pub fn export_glb_with_options<P: AsRef<Path>>(objects: &[T3dObject], path: P, options: &T3dExportOptions) -> Result<(), T3dExportError> {
+    std::fs::write(path, write_glb(objects, options)?)?;
+    Ok(())
+}
@@ -0,0 +1,33 @@
+//! JSON import and export of objects.
+//!
+//! Objects are written as a list of:
+//!
+//! ```json
+//! {
+//!   "type": "Actor",
+//!   "header": [["Class", {"type": "Identifier", "value": "Brush"}]],
+//!   "children": [],
+//!   "properties": {"CsgOper": {"type": "Value", "value": {"type": "Identifier", "value": "CSG_Subtract"}}},
+//!   "vector_properties": [["Origin", [128.0, 16.0, 16.0]]]
+//! }
+//! ```
+//!
+//! Every value is tagged with its variant (`Int`, `Float`, `Bool`, `String`, `Struct`, `InlineStruct`, `Reference`,
+//! `Identifier`, `Vector` or `Array`), so that identifiers, strings and references can be told apart. References
+//! are written as `{"type": "StaticMesh", "path": "Foo.Bar"}`, plus `"quoted": true` for UE4 paths in double quotes.
+//! Properties and struct members are sorted by name. `CustomProperties` statements are written as a
+//! `"custom_properties"` list of strings, which is omitted when empty.
+
+use crate::ast::T3dObject;
+
+pub fn to_json(objects: &[T3dObject]) -> serde_json::Result<String> {
+    serde_json::to_string(objects)
+}
+
+pub fn to_json_pretty(objects: &[T3dObject]) -> serde_json::Result<String> {
+    serde_json::to_string_pretty(objects)
+}
+
+pub fn from_json(contents: &str) -> serde_json::Result<Vec<T3dObject>> {
+    serde_json::from_str(contents)
+}
@@ -1,33 +1,39 @@
-extern crate core;
+//! A parser and writer for Unreal Engine [T3D files](https://wiki.beyondunreal.com/Legacy:T3D_File).
+//!
+//! The Python bindings are built when the `python` feature is enabled (the default). To use this crate from Rust
+//! without Python, disable the default features.
 
-mod parser;
-mod ast;
+pub mod ast;
+pub mod brush;
+pub mod csg;
+pub mod dialect;
+pub mod encoding;
+pub mod export;
+pub mod flags;
+#[cfg(feature = "serde")]
+pub mod gltf;
+pub mod parser;
+pub mod polygon;
+pub mod writer;
+#[cfg(feature = "serde")]
+pub mod json;
+#[cfg(feature = "python")]
+mod python;
 
-use pyo3::prelude::*;
-
-use pyo3::exceptions::PySyntaxError;
-use crate::ast::{T3dObject, T3dReference};
-use crate::parser::parse_t3d;
-
-#[pyfunction]
-fn read_t3d(contents: &str) -> PyResult<Vec<T3dObject>> {
-    match parse_t3d(contents) {
-        Ok(objects) => {
-            Ok(objects)
-        },
-        Err(err) => {
-            Err(PySyntaxError::new_err(format!("{:?}", err)))
-        }
-    }
-}
-
-#[pymodule]
-fn t3dpy(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
-    m.add_class::<T3dObject>()?;
-    m.add_class::<T3dReference>()?;
-    m.add_function(wrap_pyfunction!(read_t3d, m)?)?;
-    Ok(())
-}
+pub use ast::{T3dDiagnostic, T3dObject, T3dPropertyValue, T3dReference, T3dSpan, T3dStruct, T3dValue, T3dViewError};
+pub use encoding::T3dEncoding;
+pub use export::{export_obj, export_obj_with_options, write_obj, T3dExportError, T3dExportOptions};
+pub use flags::{PolyFlags, PolyFlagsLayout};
+#[cfg(feature = "serde")]
+pub use gltf::{export_glb, export_glb_with_options, write_glb};
+pub use parser::{parse_t3d, parse_t3d_bytes, parse_t3d_recovering, parse_t3d_with_options, read_t3d_file, T3dParseOptions, T3dReadError, T3dSyntaxError};
+pub use brush::{CsgOper, SheerAxis, T3dBrush, T3dScale};
+pub use csg::csg_polygons;
+pub use dialect::T3dDialect;
+pub use parser::parse_t3d as parse;
+pub use polygon::T3dPolygon;
+pub use writer::{write_t3d, write_t3d_bytes, write_t3d_file, write_t3d_with_dialect};
+pub use writer::write_t3d as write;
 
 #[cfg(test)]
 mod tests {
@@ -52,7 +58,7 @@ mod tests {
                     T3dPropertyValue::Value(v) => {
                         match v {
                             T3dValue::Array(values) => {
-                                match values.get(0).unwrap().clone().unwrap() {
+                                match values.first().unwrap().clone().unwrap() {
                                     T3dValue::Reference(reference) => {
                                         assert_eq!(reference.type_, "StaticMesh");
                                         assert_eq!(reference.path, "Foo.Bar");
@@ -109,15 +115,13 @@ mod tests {
             Ok(objects) => {
                 assert_eq!(1, objects.len());
                 let object = objects.first().unwrap();
-                assert_eq!(4, object.properties.len());
+                assert_eq!(1, object.properties.len());
+                assert_eq!(3, object.header.len());
+                assert_eq!(Some(&T3dValue::Int(32768)), object.header_value("Flags"));
                 let pan = object.properties.get("Pan").unwrap();
                 println!("{:?}", pan);
-                if let T3dPropertyValue::Value(pan) = pan {
-                    if let T3dValue::InlineStruct(pan) = pan {
-                        assert_eq!(2, pan.len());
-                    } else {
-                        return Err(String::from("Expected struct value for Pan"));
-                    }
+                if let T3dPropertyValue::Value(T3dValue::InlineStruct(pan)) = pan {
+                    assert_eq!(2, pan.len());
                 } else {
                     return Err(String::from("Expected struct value for Pan"));
                 }
@@ -144,7 +148,7 @@ mod tests {
                 let object = objects.first().unwrap();
                 assert_eq!(2, object.properties.len());
                 let value = object.properties.get("MyEmptyString");
-                assert_eq!(true, value.is_some());
+                assert!(value.is_some());
                 match value.unwrap() {
                     T3dPropertyValue::Value(value) => {
                         match value {
@@ -220,6 +224,921 @@ mod tests {
         }
     }
 
+    #[test]
+    fn sparse_arrays_and_empty_struct_members() -> Result<(), String> {
+        let objects = parse_file(String::from("src/tests/data/ue2_sparse_arrays.t3d"))?;
+        let actors = &objects[0].children;
+        let texture = |path: &str| T3dValue::Reference(T3dReference::new("Shader", path));
+        assert_eq!(
+            actors[0].properties["Skins"],
+            T3dPropertyValue::Value(T3dValue::Array(vec![None, Some(texture("HourIndusX_Tex.Pipes.PipeShader"))]))
+        );
+        assert_eq!(
+            actors[1].properties["DecoLayerOffset"],
+            T3dPropertyValue::Value(T3dValue::Array(vec![None, None, Some(T3dValue::Int(8))]))
+        );
+        let T3dPropertyValue::Array(deco_layers) = &actors[1].properties["DecoLayers"] else {
+            return Err(String::from("DecoLayers is not an array"))
+        };
+        let T3dValue::Struct(deco_layer) = &deco_layers[0].1 else {
+            return Err(String::from("DecoLayers(0) is not a struct"))
+        };
+        assert_eq!(deco_layer["DensityMap"], T3dValue::String(String::new()));
+        assert_eq!(deco_layer["ColorMap"], T3dValue::String(String::new()));
+        assert_eq!(deco_layer["ShowOnTerrain"], T3dValue::Int(1));
+        assert_eq!(deco_layer.len(), 7);
+        Ok(())
+    }
+
+    #[test]
+    fn udk_subobjects() -> Result<(), String> {
+        let objects = parse_file(String::from("src/tests/data/udk_static_mesh_actor.t3d"))?;
+        let light = &objects[0].children[0].children[1];
+        assert_eq!(light.archetype().map(|a| a.path.as_str()), Some("Engine.Default__PointLight"));
+        assert_eq!(light.subobjects().count(), 2);
+        let component = light.subobject("PointLightComponent_7").ok_or("missing subobject")?;
+        assert_eq!(component.class_name().as_deref(), Some("PointLightComponent"));
+        assert_eq!(light.subobject("PointLightComponent0"), Some(component));
+        assert_eq!(component.archetype().map(|a| a.type_.as_str()), Some("PointLightComponent"));
+        assert_eq!(component.properties["Radius"], T3dPropertyValue::Value(T3dValue::Float(512.0)));
+        Ok(())
+    }
+
+    #[test]
+    fn ue4_subobjects_and_custom_properties() -> Result<(), String> {
+        let objects = parse_file(String::from("src/tests/data/ue4_static_mesh_actor.t3d"))?;
+        let level = &objects[0].children[0];
+        let actor = &level.children[0];
+        assert_eq!(actor.class_name().as_deref(), Some("/Script/Engine.StaticMeshActor"));
+        assert_eq!(actor.archetype().map(|a| a.type_.as_str()), Some("/Script/Engine.StaticMeshActor"));
+        let declaration = actor.subobjects().next().ok_or("missing subobject")?;
+        assert_eq!(declaration.class_name().as_deref(), Some("/Script/Engine.StaticMeshComponent"));
+        let definition = actor.subobject("StaticMeshComponent0").ok_or("missing subobject")?;
+        assert_eq!(
+            definition.properties["StaticMesh"],
+            T3dPropertyValue::Value(T3dValue::Reference(T3dReference {
+                quoted: true,
+                ..T3dReference::new("StaticMesh", "/Engine/BasicShapes/Cube.Cube")
+            }))
+        );
+        let node = &level.children[1];
+        assert_eq!(node.custom_properties.len(), 2);
+        assert!(node.custom_properties[0].starts_with("Pin (PinId=A1F3C2D34B5E6F708192A3B4C5D6E7F8,PinName=\"execute\""));
+        Ok(())
+    }
+
+    #[test]
+    fn reference_paths() {
+        let contents = "Begin Object\n   A=StaticMesh'\"/Game/Meshes/SM_Wall.SM_Wall\"'\n   B=Class'/Script/Engine.Actor'\n   C=Foo'Pkg.Default__Bar:Comp_0'\n   D=Texture'UT2004.Group.Sub.Tex'\n   E=StaticMeshComponent'StaticMeshComponent_12'\nEnd Object\n";
+        let objects = parser::parse_t3d(contents).unwrap();
+        let reference = |name: &str| match &objects[0].properties[name] {
+            T3dPropertyValue::Value(T3dValue::Reference(reference)) => reference.clone(),
+            value => panic!("{} is not a reference: {:?}", name, value),
+        };
+        let a = reference("A");
+        assert!(a.quoted);
+        assert_eq!((a.package(), a.groups(), a.object_name()), (Some("/Game/Meshes/SM_Wall"), vec![], "SM_Wall"));
+        assert_eq!(a.to_string(), "StaticMesh'\"/Game/Meshes/SM_Wall.SM_Wall\"'");
+        let b = reference("B");
+        assert_eq!((b.class_name(), b.package(), b.object_name()), ("Class", Some("/Script/Engine"), "Actor"));
+        let c = reference("C");
+        assert_eq!((c.package(), c.object_name(), c.subobjects()), (Some("Pkg"), "Default__Bar", vec!["Comp_0"]));
+        let d = reference("D");
+        assert_eq!((d.package(), d.groups(), d.object_name()), (Some("UT2004"), vec!["Group", "Sub"], "Tex"));
+        let e = reference("E");
+        assert_eq!((e.package(), e.object_name()), (None, "StaticMeshComponent_12"));
+        let mut formatted = objects.clone();
+        formatted[0].layout = None;
+        assert_eq!(parser::parse_t3d(writer::write_t3d(&formatted).as_str()).unwrap(), objects);
+    }
+
+    #[test]
+    fn polygon_view() -> Result<(), String> {
+        let objects = parse_file(String::from("src/tests/data/terraininfo.t3d"))?;
+        let polygon = T3dPolygon::from_object(&objects[0]).map_err(|e| e.to_string())?;
+        assert_eq!(polygon.origin, [-1_023.999_9, 0.0, -1_056.000_1]);
+        assert_eq!(polygon.texture_u, [0.191342, 0.461940, 0.0]);
+        assert_eq!(polygon.vertices.len(), 4);
+        assert_eq!(polygon.vertices[2], [-724.077_33, 724.077_3, 495.999_94]);
+        assert_eq!((polygon.pan_u, polygon.pan_v), (57, 0));
+        assert_eq!(polygon.texture.as_deref(), Some("DecayedS.Wall.dWallA3"));
+        assert_eq!((polygon.flags, polygon.item.as_deref(), polygon.link), (PolyFlags(32768), Some("2DLoftSIDE"), None));
+
+        let objects = parser::parse_t3d("Begin Polygon Flags=-1073741824\n   Normal   +00000.000000,+00001.000000\n   Vertex   +00000.000000,+00000.000000,+00000.000000\nEnd Polygon").unwrap();
+        let error = T3dPolygon::from_object(&objects[0]).unwrap_err();
+        assert_eq!(error.message, "Normal must have 3 components, found 2");
+        assert_eq!(error.span.map(|s| (s.start_line, s.start_column)), Some((2, 4)));
+
+        let objects = parser::parse_t3d("Begin Polygon Flags=-1073741824\n   Vertex   +00000.000000,+00000.000000,+00000.000000\n   Vertex   +00128.000000,+00000.000000,+00000.000000\n   Vertex   +00000.000000,+00128.000000,+00000.000000\n   SurfaceColor   +00000.000000,+00000.000000,+00000.000000,+00000.000000\nEnd Polygon").unwrap();
+        let polygon = T3dPolygon::from_object(&objects[0]).map_err(|e| e.to_string())?;
+        assert_eq!(polygon.flags, PolyFlags(0xC0000000));
+        assert_eq!(polygon.normal, [0.0, 0.0, 1.0]);
+        Ok(())
+    }
+
+    #[test]
+    fn brush_view() -> Result<(), String> {
+        let objects = parse_file(String::from("src/tests/data/DMDeathFan.t3d"))?;
+        let actor = |name: &str| objects[0].children.iter().find(|a| a.name().as_deref() == Some(name)).unwrap();
+        let brush = T3dBrush::from_object(actor("Brush12")).map_err(|e| e.to_string())?;
+        assert_eq!(brush.csg_oper, CsgOper::Add);
+        assert_eq!(brush.location, [1272.0, -128.0, 1540.0]);
+        assert_eq!(brush.pre_pivot, [1272.0, -896.0, 204.0]);
+        assert_eq!((brush.rotation, brush.main_scale), ([0.0; 3], T3dScale::default()));
+        assert_eq!(brush.polygons.len(), 6);
+        assert_eq!(brush.polygons[0].texture.as_deref(), Some("DecayedS.Base.Dterbas1"));
+
+        let mover = T3dBrush::from_object(actor("Mover0")).map_err(|e| e.to_string())?;
+        assert_eq!(mover.csg_oper, CsgOper::Active);
+        assert_eq!(mover.rotation, [0.0, 49152.0, 0.0]);
+        assert_eq!(mover.main_scale, T3dScale { scale: [1.0; 3], sheer_rate: 0.0, sheer_axis: SheerAxis::ZX });
+
+        let objects = parser::parse_t3d("Begin Actor Class=Brush Name=Brush1
+   MainScale=(Scale=(X=-1,Z=2.5),SheerRate=0.5,SheerAxis=SHEER_XY)
+   PolyFlags=8
+   Begin Brush Name=Model1
+   End Brush
+End Actor").unwrap();
+        let brush = T3dBrush::from_object(&objects[0]).map_err(|e| e.to_string())?;
+        assert_eq!(brush.main_scale, T3dScale { scale: [-1.0, 1.0, 2.5], sheer_rate: 0.5, sheer_axis: SheerAxis::XY });
+        assert_eq!((brush.poly_flags, brush.polygons.len()), (PolyFlags::NOT_SOLID, 0));
+        assert!(T3dBrush::from_object(actor("LevelInfo0")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn poly_flags() {
+        let flags = PolyFlags(0x0040_0104);
+        assert_eq!(flags.names(PolyFlagsLayout::Ue1), ["Translucent", "TwoSided", "Unlit"]);
+        assert_eq!(flags.names(PolyFlagsLayout::Ue2), ["0x00000004", "TwoSided", "Unlit"]);
+        assert_eq!(PolyFlags::from_names(["translucent", "TwoSided", "Unlit"], PolyFlagsLayout::Ue1), Ok(flags));
+        assert_eq!(PolyFlags::from_names(flags.names(PolyFlagsLayout::Ue2), PolyFlagsLayout::Ue2), Ok(flags));
+        assert_eq!(PolyFlags::from_names(["Masked"], PolyFlagsLayout::Ue2), Err(String::from("unknown flag: Masked")));
+        assert_eq!(PolyFlags(0x0000_0040).names(PolyFlagsLayout::Ue2), ["GeomMarked"]);
+        assert_eq!(PolyFlags::from_name("EdProcessed", PolyFlagsLayout::Ue1), PolyFlags::from_name("FlatShaded", PolyFlagsLayout::Ue1));
+        assert!(flags.contains(PolyFlags::TWO_SIDED | PolyFlags::UNLIT) && !flags.intersects(PolyFlags::PORTAL));
+        assert_eq!(PolyFlags(0xC000_0000).to_string(), "-1073741824");
+    }
+
+    #[test]
+    fn apply_poly_flags() -> Result<(), String> {
+        let contents = "Begin Actor Class=Brush Name=Brush1
+   polyflags=8
+   Begin Brush Name=Model1
+      Begin PolyList
+         Begin Polygon Texture=Wall Flags=256 Link=0
+            Vertex   +00000.000000,+00000.000000,+00000.000000
+            Vertex   +00128.000000,+00000.000000,+00000.000000
+            Vertex   +00000.000000,+00128.000000,+00000.000000
+         End Polygon
+         Begin Polygon Texture=Floor
+            Vertex   +00000.000000,+00000.000000,+00000.000000
+            Vertex   +00128.000000,+00000.000000,+00000.000000
+            Vertex   +00000.000000,+00128.000000,+00000.000000
+         End Polygon
+      End PolyList
+   End Brush
+End Actor
+";
+        let options = parser::T3dParseOptions { lossless: true, ..Default::default() };
+        let mut objects = parser::parse_t3d_with_options(contents, options).unwrap();
+        let mut brush = T3dBrush::from_object(&objects[0]).map_err(|e| e.to_string())?;
+        brush.apply_to(&mut objects[0]).map_err(|e| e.to_string())?;
+        assert_eq!(writer::write_t3d(&objects), contents);
+
+        brush.poly_flags = PolyFlags::SEMISOLID;
+        brush.polygons[0].flags = PolyFlags(0xC000_0000);
+        brush.polygons[1].flags = PolyFlags::TWO_SIDED;
+        brush.apply_to(&mut objects[0]).map_err(|e| e.to_string())?;
+        let expected = contents.replace("polyflags=8", "polyflags=32")
+            .replace("Flags=256 Link=0", "Flags=-1073741824 Link=0")
+            .replace("Texture=Floor", "Texture=Floor Flags=256");
+        assert_eq!(writer::write_t3d(&objects), expected);
+        let objects = parser::parse_t3d(expected.as_str()).unwrap();
+        assert_eq!(T3dBrush::from_object(&objects[0]).map_err(|e| e.to_string())?, brush);
+
+        let mut objects = parser::parse_t3d(contents).unwrap();
+        brush.poly_flags = PolyFlags::default();
+        brush.polygons[0].flags = PolyFlags::default();
+        brush.apply_to(&mut objects[0]).map_err(|e| e.to_string())?;
+        assert!(objects[0].property("PolyFlags").is_none());
+        assert!(objects[0].children[0].children[0].children[0].header_value("Flags").is_none());
+        brush.polygons.pop();
+        assert!(brush.apply_to(&mut objects[0]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn case_insensitive_properties() -> Result<(), String> {
+        let mut objects = parser::parse_t3d("Begin Actor Class=Brush Name=Brush1
+   csgoper=CSG_Subtract
+   Location=(x=16,Y=32)
+   Begin Brush Name=Model1
+   End Brush
+End Actor").unwrap();
+        let object = &mut objects[0];
+        assert_eq!(object.properties.get("CsgOper"), None);
+        assert_eq!(object.property_key("CSGOPER"), Some("csgoper"));
+        assert_eq!(object.property("CsgOper"), Some(&T3dPropertyValue::Value(T3dValue::Identifier(String::from("CSG_Subtract")))));
+        assert_eq!(object.property_span("CsgOper", None).map(|s| s.start_line), Some(2));
+        assert_eq!(object.property("Rotation"), None);
+        let brush = T3dBrush::from_object(object).map_err(|e| e.to_string())?;
+        assert_eq!((brush.csg_oper, brush.location), (CsgOper::Subtract, [16.0, 32.0, 0.0]));
+
+        *object.property_mut("CSGOPER").unwrap() = T3dPropertyValue::Value(T3dValue::Identifier(String::from("CSG_Add")));
+        assert!(write_t3d(&objects).contains("   csgoper=CSG_Add\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn dialects() -> Result<(), String> {
+        let detect = |path: &str| parse_file(format!("src/tests/data/{}", path)).map(|objects| T3dDialect::detect(&objects));
+        assert_eq!(detect("DMDeathFan.t3d")?, Some(T3dDialect::Ue1));
+        assert_eq!(detect("ue2_sparse_arrays.t3d")?, Some(T3dDialect::Ue2));
+        assert_eq!(detect("mirra.t3d")?, Some(T3dDialect::Ue2));
+        assert_eq!(detect("udk_static_mesh_actor.t3d")?, Some(T3dDialect::Ue3));
+        assert_eq!(detect("ue4_static_mesh_actor.t3d")?, Some(T3dDialect::Ue4));
+        let objects = parser::parse_t3d("Begin Actor Class=ROEngine.ROObjective Name=ROObjective0\nEnd Actor").unwrap();
+        assert_eq!(T3dDialect::detect(&objects), Some(T3dDialect::Ue25));
+        assert_eq!(T3dDialect::detect(&[]), None);
+        assert_eq!(T3dDialect::from_name("ue2.5"), Some(T3dDialect::Ue25));
+
+        // UE1 strings are verbatim, and UE1 cannot read packages in classes or later polygon headers.
+        let contents = "Begin Actor Class=Engine.Brush Name=Brush0
+   Group=\"C:\\UT\\Maps\"
+   Begin Polygon Texture=Wall Flags=8 LightMapScale=32.000000
+      Vertex   +00000.000000,+00000.000000,+00000.000000
+      SurfaceColor   +00000.000000,+00000.000000,+00000.000000,+00000.000000
+   End Polygon
+End Actor
+";
+        let options = T3dParseOptions { dialect: Some(T3dDialect::Ue1), ..Default::default() };
+        let objects = parser::parse_t3d_with_options(contents, options).unwrap();
+        assert_eq!(objects[0].properties.get("Group"), Some(&T3dPropertyValue::Value(T3dValue::String(String::from("C:\\UT\\Maps")))));
+        assert_eq!(write_t3d_with_dialect(&objects, T3dDialect::Ue1), "Begin Actor Class=Brush Name=Brush0
+   Group=\"C:\\UT\\Maps\"
+   Begin Polygon Texture=Wall Flags=8
+      Vertex   +00000.000000,+00000.000000,+00000.000000
+   End Polygon
+End Actor
+");
+        assert!(write_t3d_with_dialect(&objects, T3dDialect::Ue3).contains("Group=\"C:\\\\UT\\\\Maps\""));
+        // Without a dialect, strings are only unescaped if the text is detected to be from UE3 or later.
+        let objects = parser::parse_t3d(contents).unwrap();
+        assert_eq!(objects[0].properties.get("Group"), Some(&T3dPropertyValue::Value(T3dValue::String(String::from("C:\\UT\\Maps")))));
+        assert!(write_t3d(&objects).contains("Group=\"C:\\UT\\Maps\""));
+
+        // UE4 brushes are placed by their component, with rotations in degrees.
+        let objects = parser::parse_t3d("Begin Actor Class=/Script/Engine.Brush Name=Brush_1
+   Begin Object Class=/Script/Engine.BrushComponent Name=\"BrushComponent0\"
+   End Object
+   Begin Object Name=\"BrushComponent0\"
+      RelativeLocation=(X=16.000000,Y=32.000000,Z=64.000000)
+      RelativeRotation=(Pitch=0.000000,Yaw=90.000000,Roll=0.000000)
+      RelativeScale3D=(X=2.000000,Y=1.000000,Z=1.000000)
+   End Object
+   Begin Brush Name=Brush_1
+      Begin PolyList
+         Begin Polygon Flags=8
+            Vertex   +00000.000000,+00000.000000,+00000.000000
+            Vertex   +00001.000000,+00000.000000,+00000.000000
+            Vertex   +00000.000000,+00001.000000,+00000.000000
+         End Polygon
+      End PolyList
+   End Brush
+End Actor").unwrap();
+        let brush = T3dBrush::from_object_with_dialect(&objects[0], T3dDialect::Ue4).map_err(|e| e.to_string())?;
+        assert_eq!((brush.location, brush.rotation), ([16.0, 32.0, 64.0], [0.0, 16384.0, 0.0]));
+        assert_eq!((brush.main_scale.scale, brush.flags_layout), ([2.0, 1.0, 1.0], PolyFlagsLayout::Ue2));
+        assert_eq!(brush.polygons[0].flags.names(brush.polygons[0].flags_layout), ["NotSolid"]);
+        assert_vectors_eq(&brush.world_polygons()[0].vertices, &[[16.0, 32.0, 64.0], [16.0, 34.0, 64.0], [15.0, 32.0, 64.0]]);
+        Ok(())
+    }
+
+    fn assert_vectors_eq(actual: &[[f32; 3]], expected: &[[f32; 3]]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!(a.iter().zip(e).all(|(a, e)| (a - e).abs() < 1e-3), "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn brush_world_polygons() -> Result<(), String> {
+        let objects = parse_file(String::from("src/tests/data/DMDeathFan.t3d"))?;
+        let actor = |name: &str| objects[0].children.iter().find(|a| a.name().as_deref() == Some(name)).unwrap();
+
+        // Brush12 is only offset: Location - PrePivot = (0, 768, 1336).
+        let polygons = T3dBrush::from_object(actor("Brush12")).map_err(|e| e.to_string())?.world_polygons();
+        assert_vectors_eq(&polygons[0].vertices, &[
+            [128.0, 512.0, 1344.0], [128.0, 1024.0, 1344.0], [-128.0, 1024.0, 1344.0], [-128.0, 512.0, 1344.0],
+        ]);
+        assert_vectors_eq(&[polygons[0].origin, polygons[0].normal], &[[0.0, 0.0, 1344.0], [0.0, 0.0, 1.0]]);
+
+        // Mover0 has PrePivot (8, 4, 8), Yaw 49152 (270 degrees, so (x, y, z) becomes (y, -x, z)) and Location
+        // (1040, 0, 1344).
+        let polygons = T3dBrush::from_object(actor("Mover0")).map_err(|e| e.to_string())?.world_polygons();
+        assert_vectors_eq(&polygons[0].vertices, &[
+            [1124.0, -256.0, 1336.0], [1124.0, 256.0, 1336.0], [1140.0, 256.0, 1336.0], [1140.0, -256.0, 1336.0],
+        ]);
+        assert_vectors_eq(&[polygons[0].normal, polygons[0].texture_u, polygons[0].texture_v], &[
+            [0.0, 0.0, -1.0], [1.0, 0.0, 0.0], [0.0, -1.0, 0.0],
+        ]);
+        assert_vectors_eq(&[polygons[1].normal, polygons[1].texture_u], &[[-1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]);
+
+        // Mirroring reverses the winding; SheerRate=0.5 snaps to 0.45, and SHEER_XY adds 0.45 * Y to X.
+        let objects = parser::parse_t3d("Begin Actor Class=Brush Name=Brush1
+   MainScale=(Scale=(X=-1,Z=2.5),SheerRate=0.5,SheerAxis=SHEER_XY)
+   Begin Brush Name=Model1
+      Begin PolyList
+         Begin Polygon
+            Origin   +00000.000000,+00000.000000,+00000.000000
+            Normal   +00000.000000,+00000.000000,+00001.000000
+            TextureU +00001.000000,+00000.000000,+00000.000000
+            Vertex   +00000.000000,+00000.000000,+00000.000000
+            Vertex   +00001.000000,+00000.000000,+00000.000000
+            Vertex   +00000.000000,+00001.000000,+00000.000000
+         End Polygon
+      End PolyList
+   End Brush
+End Actor").unwrap();
+        let polygon = &T3dBrush::from_object(&objects[0]).map_err(|e| e.to_string())?.world_polygons()[0];
+        assert_vectors_eq(&polygon.vertices, &[[0.45, 1.0, 0.0], [-1.0, 0.0, 0.0], [0.0, 0.0, 0.0]]);
+        assert_vectors_eq(&[polygon.normal, polygon::polygon_normal(&polygon.vertices)], &[[0.0, 0.0, 1.0]; 2]);
+        // Texture coordinates are unchanged: (P - Origin) . TextureU is still 1 for the second local vertex.
+        let u: f32 = polygon.vertices[1].iter().zip(polygon.texture_u).map(|(p, t)| p * t).sum();
+        assert!((u - 1.0).abs() < 1e-5);
+        Ok(())
+    }
+
+    #[test]
+    fn obj_export() -> Result<(), String> {
+        let objects = parser::parse_t3d("Begin Map
+Begin Actor Class=Brush Name=Brush1
+   Location=(X=100)
+   Begin Brush Name=Model1
+      Begin PolyList
+         Begin Polygon Texture=Floor
+            Origin   +00000.000000,+00000.000000,+00000.000000
+            Normal   +00000.000000,+00000.000000,+00001.000000
+            TextureU +00001.000000,+00000.000000,+00000.000000
+            TextureV +00000.000000,+00001.000000,+00000.000000
+            Pan      U=64 V=0
+            Vertex   +00000.000000,+00000.000000,+00000.000000
+            Vertex   +00128.000000,+00000.000000,+00000.000000
+            Vertex   +00128.000000,+00128.000000,+00000.000000
+            Vertex   +00000.000000,+00128.000000,+00000.000000
+         End Polygon
+         Begin Polygon
+            Vertex   +00000.000000,+00000.000000,+00000.000000
+            Vertex   +00000.000000,+00000.000000,+00001.000000
+            Vertex   +00000.000000,+00001.000000,+00000.000000
+         End Polygon
+      End PolyList
+   End Brush
+End Actor
+End Map").unwrap();
+        let options = T3dExportOptions { texture_sizes: [(String::from("Floor"), (128, 128))].into(), ..Default::default() };
+        let (obj, mtl) = write_obj(&objects, "map.mtl", &options).map_err(|e| e.to_string())?;
+        assert_eq!(obj, "mtllib map.mtl
+g Brush1
+usemtl Floor
+v 100 0 0
+vt 0.5 0
+v 228 0 0
+vt 1.5 0
+v 228 -128 0
+vt 1.5 -1
+v 100 -128 0
+vt 0.5 -1
+vn 0 0 1
+f 1/1/1 3/3/1 2/2/1
+f 1/1/1 4/4/1 3/3/1
+usemtl None
+v 100 0 0
+vt 0 0
+v 100 0 1
+vt 0 0
+v 100 -1 0
+vt 0 0
+vn -1 0 0
+f 5/5/2 7/7/2 6/6/2
+");
+        assert_eq!(mtl, "newmtl Floor\nKd 0.8 0.8 0.8\nnewmtl None\nKd 0.8 0.8 0.8\n");
+
+        let objects = parse_file(String::from("src/tests/data/DMDeathFan.t3d"))?;
+        let (obj, _) = write_obj(&objects, "DMDeathFan.mtl", &T3dExportOptions::default()).map_err(|e| e.to_string())?;
+        assert!(obj.contains("g Brush12\nusemtl DecayedS.Base.Dterbas1\nv 128 -512 1344\n"));
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn glb_export() -> Result<(), String> {
+        let objects = parse_file(String::from("src/tests/data/DMDeathFan.t3d"))?;
+        let glb = write_glb(&objects, &T3dExportOptions::default()).map_err(|e| e.to_string())?;
+        let u32_at = |offset: usize| u32::from_le_bytes(glb[offset..offset + 4].try_into().unwrap()) as usize;
+        assert_eq!((&glb[0..4], u32_at(4), u32_at(8)), (&b"glTF"[..], 2, glb.len()));
+        let json_length = u32_at(12);
+        assert_eq!(&glb[16..20], b"JSON");
+        let gltf: serde_json::Value = serde_json::from_slice(&glb[20..20 + json_length]).map_err(|e| e.to_string())?;
+        let bin_length = u32_at(20 + json_length);
+        assert_eq!(&glb[24 + json_length..28 + json_length], b"BIN\0");
+        assert_eq!(gltf["buffers"][0]["byteLength"].as_u64(), Some(bin_length as u64));
+
+        let nodes = gltf["nodes"].as_array().unwrap();
+        let node = |name: &str| nodes.iter().find(|node| node["name"] == name).unwrap();
+        assert_eq!(node("LevelInfo0")["extras"]["class"], "LevelInfo");
+        assert!(node("LevelInfo0").get("mesh").is_none());
+
+        // Brush12 has no rotation, so its vertices are only offset by -PrePivot, with Y and Z swapped.
+        let brush = node("Brush12");
+        assert_eq!(brush["translation"], serde_json::json!([1272.0, 1540.0, -128.0]));
+        assert_eq!(brush["extras"]["properties"]["CsgOper"]["value"]["value"], "CSG_Add");
+        let primitive = &gltf["meshes"][brush["mesh"].as_u64().unwrap() as usize]["primitives"][0];
+        let position = &gltf["accessors"][primitive["attributes"]["POSITION"].as_u64().unwrap() as usize];
+        assert_eq!(position["min"], serde_json::json!([-1400.0, -212.0, 640.0]));
+        assert_eq!(position["max"], serde_json::json!([-1144.0, -196.0, 1152.0]));
+        assert_eq!(gltf["materials"][primitive["material"].as_u64().unwrap() as usize]["name"], "DecayedS.Base.Dterbas1");
+
+        // Yaw 49152 turns X towards -Y in Unreal, which is a quarter turn about the glTF Y axis.
+        let rotation: Vec<f64> = node("Mover0")["rotation"].as_array().unwrap().iter().map(|c| c.as_f64().unwrap()).collect();
+        let expected = [0.0, std::f64::consts::FRAC_1_SQRT_2, 0.0, std::f64::consts::FRAC_1_SQRT_2];
+        assert!(rotation.iter().zip(expected).all(|(a, e)| (a - e).abs() < 1e-5), "{:?}", rotation);
+
+        // UE4 actors are placed by their root component.
+        let gltf_json = |objects: &[T3dObject]| -> Result<serde_json::Value, String> {
+            let glb = write_glb(objects, &T3dExportOptions::default()).map_err(|e| e.to_string())?;
+            let json_length = u32::from_le_bytes(glb[12..16].try_into().unwrap()) as usize;
+            serde_json::from_slice(&glb[20..20 + json_length]).map_err(|e| e.to_string())
+        };
+        let gltf = gltf_json(&parse_file(String::from("src/tests/data/ue4_static_mesh_actor.t3d"))?)?;
+        assert_eq!(gltf["nodes"][0]["translation"], serde_json::json!([-140.0, 0.0, 50.0]));
+
+        // A brush with a zero scale has the scale baked into its vertices instead of producing NaNs.
+        let contents = cube_brush("Flat", "CSG_Add", [0.0; 3], 16.0, 0).replace("Begin Brush", "DrawScale=0.000000\nBegin Brush");
+        let gltf = gltf_json(&parser::parse_t3d(contents.as_str()).unwrap())?;
+        assert_eq!(gltf["nodes"][0]["scale"], serde_json::json!([1.0, 1.0, 1.0]));
+        assert_eq!(gltf["accessors"][0]["max"], serde_json::json!([16.0, 16.0, 16.0]));
+        Ok(())
+    }
+
+    /// Writes a cube brush with the given half size, with polygons facing outwards.
+    fn cube_brush(name: &str, csg_oper: &str, location: [f32; 3], half: f32, poly_flags: u32) -> String {
+        let corner = |i: usize| [i & 1, i & 2, i & 4].map(|bit| if bit != 0 { half } else { -half });
+        let faces = [[0, 4, 6, 2], [1, 3, 7, 5], [0, 1, 5, 4], [2, 6, 7, 3], [0, 2, 3, 1], [4, 5, 7, 6]];
+        let mut polygons = String::new();
+        for face in faces {
+            polygons.push_str(format!("Begin Polygon Texture={}\n", name).as_str());
+            for i in face {
+                let [x, y, z] = corner(i);
+                polygons.push_str(format!("Vertex {:+013.6},{:+013.6},{:+013.6}\n", x, y, z).as_str());
+            }
+            polygons.push_str("End Polygon\n");
+        }
+        format!("Begin Actor Class=Brush Name={}\nCsgOper={}\nPolyFlags={}\nLocation=(X={},Y={},Z={})\nBegin Brush Name=Model{}\nBegin PolyList\n{}End PolyList\nEnd Brush\nEnd Actor\n",
+            name, csg_oper, poly_flags, location[0], location[1], location[2], name, polygons)
+    }
+
+    #[test]
+    fn csg() -> Result<(), String> {
+        // A room with a pillar standing on its floor, a semisolid cube floating in the room and another one buried
+        // in solid space. The builder brush, mover and volume are skipped, even though they are additive.
+        let contents = [
+            cube_brush("Builder", "CSG_Add", [0.0; 3], 1000.0, 0).replace("Name=ModelBuilder", "Name=Brush"),
+            cube_brush("Room", "CSG_Subtract", [0.0; 3], 128.0, 0),
+            cube_brush("Pillar", "CSG_Add", [0.0, 0.0, -96.0], 32.0, 0),
+            cube_brush("Floating", "CSG_Add", [64.0, 64.0, 0.0], 16.0, 32),
+            cube_brush("Buried", "CSG_Add", [0.0, 0.0, 300.0], 16.0, 32),
+            cube_brush("Lift", "CSG_Add", [-64.0, -64.0, 0.0], 16.0, 0).replace("Class=Brush", "Class=Engine.ElevatorMover"),
+            cube_brush("Water", "CSG_Add", [0.0, 0.0, -100.0], 200.0, 0).replace("Class=Brush", "Class=WaterVolume"),
+        ].concat();
+        let objects = parser::parse_t3d(format!("Begin Map\n{}End Map\n", contents).as_str()).unwrap();
+        let polygons = csg_polygons(&objects).map_err(|e| e.to_string())?;
+        let area_vector = |polygon: &T3dPolygon| {
+            let v = &polygon.vertices;
+            (1..v.len() - 1).fold([0.0f32; 3], |sum, i| {
+                let (a, b) = ([0, 1, 2].map(|k| v[i][k] - v[0][k]), [0, 1, 2].map(|k| v[i + 1][k] - v[0][k]));
+                let cross = [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]];
+                [0, 1, 2].map(|k| sum[k] + cross[k] / 2.0)
+            })
+        };
+        let area = |texture: &str| polygons.iter()
+            .filter(|polygon| polygon.texture.as_deref() == Some(texture))
+            .map(|polygon| area_vector(polygon).iter().map(|c| c * c).sum::<f32>().sqrt())
+            .sum::<f32>();
+        // The floor under the pillar and the bottom of the pillar are both removed.
+        assert_eq!(area("Room"), 6.0 * 256.0 * 256.0 - 64.0 * 64.0);
+        assert_eq!(area("Pillar"), 5.0 * 64.0 * 64.0);
+        assert_eq!(area("Floating"), 6.0 * 32.0 * 32.0);
+        assert_eq!(area("Buried"), 0.0);
+        assert_eq!(area("Builder"), 0.0);
+        assert_eq!(area("Lift"), 0.0);
+        assert_eq!(area("Water"), 0.0);
+        // The room faces inwards, so its floor faces up, and the top of the pillar faces up too.
+        for polygon in &polygons {
+            let normal = area_vector(polygon);
+            let length = normal.iter().map(|c| c * c).sum::<f32>().sqrt();
+            assert!((0..3).all(|k| (normal[k] / length - polygon.normal[k]).abs() < 1e-4));
+            let z = polygon.vertices[0][2];
+            if (z == -128.0 || z == -64.0) && polygon.vertices.iter().all(|v| v[2] == z) {
+                assert_eq!(polygon.normal, [0.0, 0.0, 1.0]);
+            }
+        }
+
+        // A pasted selection has no builder brush, so its first brush is kept.
+        let objects = parser::parse_t3d(cube_brush("Room", "CSG_Subtract", [0.0; 3], 128.0, 0).as_str()).unwrap();
+        assert_eq!(csg_polygons(&objects).map_err(|e| e.to_string())?.len(), 6);
+
+        let objects = parse_file(String::from("src/tests/data/DMDeathFan.t3d"))?;
+        assert!(!csg_polygons(&objects).map_err(|e| e.to_string())?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn header_attributes() {
+        let contents = "Begin Actor Class=Engine.Brush Name=Brush15
+   Name=SomethingElse
+   Begin Brush Name=Model16
+   End Brush
+End Actor";
+        let objects = parser::parse_t3d(contents).unwrap();
+        let object = objects.first().unwrap();
+        assert_eq!(object.class_name().as_deref(), Some("Engine.Brush"));
+        assert_eq!(object.name().as_deref(), Some("Brush15"));
+        assert_eq!(object.properties.get("Name"), Some(&T3dPropertyValue::Value(T3dValue::Identifier(String::from("SomethingElse")))));
+        assert_eq!(object.children.first().unwrap().name().as_deref(), Some("Model16"));
+    }
+
+    #[test]
+    fn spans() -> Result<(), String> {
+        let objects = parse_file("src/tests/data/mirra.t3d".to_string())?;
+        let brush = objects[0].children.iter().find(|o| o.name().as_deref() == Some("Brush15")).unwrap();
+        let span = brush.span.unwrap();
+        assert_eq!((span.start_line, span.start_column), (21, 1));
+        assert_eq!(brush.begin_span.unwrap().end_column, "Begin Actor Class=Engine.Brush Name=Brush15".len() + 1);
+        let end_span = brush.end_span.unwrap();
+        assert_eq!((end_span.start_line, end_span.start_column, end_span.end_column), (208, 1, 10));
+        let csg_oper = brush.property_span("CsgOper", None).unwrap();
+        assert_eq!((csg_oper.start_line, csg_oper.start_column), (22, 5));
+        let polygon = &brush.children[0].children[0].children[1];
+        let vertex = polygon.vector_property_spans[4];
+        assert_eq!((vertex.start_line, vertex.start_column), (48, 14));
+        Ok(())
+    }
+
+    #[test]
+    fn array_property_spans() {
+        let contents = "Begin Actor
+   Skins(1)=Texture'Foo.Bar'
+   Skins(0)=Texture'Foo.Baz'
+End Actor";
+        let objects = parser::parse_t3d(contents).unwrap();
+        let object = objects.first().unwrap();
+        assert_eq!(object.property_span("Skins", Some(0)).unwrap().start_line, 3);
+        assert_eq!(object.property_span("Skins", Some(1)).unwrap().start_line, 2);
+        assert_eq!(object.property_span("Skins", None).unwrap().start_line, 2);
+        assert!(object.property_span("Skins", Some(2)).is_none());
+    }
+
+    #[test]
+    fn syntax_error_location() {
+        let contents = "Begin Actor Class=Light Name=Light0
+   LightHue=12
+   Tag=\"Oops
+End Actor";
+        let error = parser::parse_t3d(contents).unwrap_err();
+        assert_eq!(parser::error_line_column(&error), (3, 8));
+        assert_eq!(error.line(), "   Tag=\"Oops");
+        assert_eq!(parser::error_expected_rules(&error), vec![String::from("value")]);
+        assert_eq!(parser::error_line_text(&error, contents), "   Tag=\"Oops");
+
+        let contents = "Begin Actor Class=Light Name=Light0\r\n   Foo=(\r\nEnd Actor";
+        let error = parser::parse_t3d(contents).unwrap_err();
+        assert_eq!(parser::error_line_column(&error), (2, 9));
+        assert_eq!(parser::error_line_text(&error, contents), "   Foo=(");
+    }
+
+    #[test]
+    fn recover_malformed_actors() {
+        let contents = "Begin Map
+Begin Actor Class=Light Name=Light0
+   Tag=\"Oops
+End Actor
+Begin Actor Class=Light Name=Light1
+   LightHue=12
+End Actor
+Begin Actor Class=Light Name=Light2
+   Begin Object Class=Foo
+   LightHue=(
+End Actor
+Begin Actor Class=Light Name=Light3
+   LightHue=12
+End Map
+";
+        let (objects, diagnostics) = parser::parse_t3d_recovering(contents, parser::T3dParseOptions::default());
+        let names: Vec<String> = objects[0].children.iter().filter_map(|o| o.name()).collect();
+        assert_eq!(names, vec![String::from("Light1")]);
+        let lines: Vec<(usize, usize)> = diagnostics.iter().map(|d| (d.span.start_line, d.span.end_line)).collect();
+        assert_eq!(lines, vec![(2, 4), (8, 11), (12, 13)]);
+        assert!(diagnostics[0].message.starts_with("Skipped `Begin Actor Class=Light Name=Light0`: expected value at line 3, column 8"));
+        assert_eq!(objects[0].children[0].span.unwrap().start_line, 5);
+    }
+
+    #[test]
+    fn recover_tab_separated_blocks() {
+        let contents = "Begin Map
+Begin\tActor Class=Light Name=Light0
+   Tag=\"Oops
+End\tActor
+begin  actor Class=Light Name=Light1
+   LightHue=12
+end  actor
+End Map
+";
+        let (objects, diagnostics) = parser::parse_t3d_recovering(contents, parser::T3dParseOptions::default());
+        let names: Vec<String> = objects[0].children.iter().filter_map(|o| o.name()).collect();
+        assert_eq!(names, vec![String::from("Light1")]);
+        let lines: Vec<(usize, usize)> = diagnostics.iter().map(|d| (d.span.start_line, d.span.end_line)).collect();
+        assert_eq!(lines, vec![(2, 4)]);
+    }
+
+    #[test]
+    fn recover_large_file() -> Result<(), String> {
+        let contents = std::fs::read_to_string("src/tests/data/MG_NTW2_Lib_Stairway.t3d").map_err(|e| e.to_string())?;
+        let expected = parser::parse_t3d(contents.as_str()).map_err(|e| e.to_string())?[0].children.len();
+        let broken = contents.replacen("CsgOper=CSG_Subtract", "CsgOper=\"CSG_Subtract", 1);
+        assert!(parser::parse_t3d(broken.as_str()).is_err());
+        let (objects, diagnostics) = parser::parse_t3d_recovering(broken.as_str(), parser::T3dParseOptions::default());
+        assert_eq!(objects[0].children.len(), expected - 1);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].span.start_line, 39);
+        Ok(())
+    }
+
+    #[test]
+    fn write_polygon() {
+        let contents = "Begin Polygon Item=2DLoftSIDE Texture=DecayedS.Wall.dWallA3 Flags=32768
+   Origin   -01023.999878,+00000.000000,-01056.000122
+   Pan      U=57 V=99
+   Vertex   -01024.000000,+00000.000000,-00016.000090
+End Polygon
+";
+        let objects = parser::parse_t3d(contents).unwrap();
+        assert_eq!(writer::write_t3d(&objects), "Begin Polygon Item=2DLoftSIDE Texture=DecayedS.Wall.dWallA3 Flags=32768
+   Origin   -01023.999878,+00000.000000,-01056.000122
+   Vertex   -01024.000000,+00000.000000,-00016.000090
+   Pan U=57 V=99
+End Polygon
+");
+    }
+
+    #[test]
+    fn write_array_properties() {
+        let contents = "Begin Actor Class=Brush Name=Brush0
+   Skins(1)=Texture'Foo.Bar'
+   Skins(0)=Texture'Foo.Baz'
+   Tag=\"Some \"
+   Location=(X=1.5,Y=-2,Z=0.0000001)
+   bHidden=True
+End Actor
+";
+        let objects = parser::parse_t3d(contents).unwrap();
+        assert_eq!(writer::write_t3d(&objects), "Begin Actor Class=Brush Name=Brush0
+   Location=(X=1.500000,Y=-2,Z=0.0000001)
+   Skins(1)=Texture'Foo.Bar'
+   Skins(0)=Texture'Foo.Baz'
+   Tag=\"Some \"
+   bHidden=True
+End Actor
+");
+    }
+
+    #[test]
+    fn write_round_trip() -> Result<(), String> {
+        for path in ["src/tests/data/DMDeathFan.t3d", "src/tests/data/MG_NTW2_Lib_Stairway.t3d", "src/tests/data/mirra.t3d", "src/tests/data/ue4_static_mesh_actor.t3d"] {
+            let objects = parse_file(path.to_string())?;
+            let written = writer::write_t3d(&objects);
+            let reparsed = parser::parse_t3d(written.as_str()).map_err(|e| e.to_string())?;
+            assert_eq!(objects, reparsed);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn encodings() -> Result<(), String> {
+        let contents = std::fs::read_to_string("src/tests/data/DMDeathFan.t3d").map_err(|e| e.to_string())?;
+        let objects = parse_t3d(contents.as_str()).map_err(|e| e.to_string())?;
+        for encoding in [T3dEncoding::Utf8, T3dEncoding::Utf8Bom, T3dEncoding::Utf16Le, T3dEncoding::Utf16Be, T3dEncoding::Utf16LeNoBom, T3dEncoding::Utf16BeNoBom] {
+            let bytes = encoding::encode(contents.as_str(), encoding);
+            let (decoded, detected) = parse_t3d_bytes(bytes.as_slice()).map_err(|e| e.to_string())?;
+            assert_eq!(detected, encoding);
+            assert_eq!(decoded, objects);
+            assert_eq!(write_t3d_bytes(&decoded, encoding), encoding::encode(write_t3d(&objects).as_str(), encoding));
+        }
+        // UTF-16 without a byte order mark is written back without one.
+        let options = parser::T3dParseOptions { lossless: true, ..Default::default() };
+        for (encoding, without_bom) in [(T3dEncoding::Utf16Le, T3dEncoding::Utf16LeNoBom), (T3dEncoding::Utf16Be, T3dEncoding::Utf16BeNoBom)] {
+            let bytes = encoding::encode(contents.as_str(), encoding);
+            let (decoded, detected) = parser::parse_t3d_bytes_with_options(&bytes[2..], options).map_err(|e| e.to_string())?;
+            assert_eq!(detected, without_bom);
+            assert_eq!(decoded, objects);
+            assert_eq!(write_t3d_bytes(&decoded, detected), &bytes[2..]);
+        }
+        let bytes = b"Begin Actor Class=Light Name=Light0\n   Tag=\"Caf\xe9 \x80\x93\"\nEnd Actor\n";
+        let (objects, encoding) = parse_t3d_bytes(bytes).map_err(|e| e.to_string())?;
+        assert_eq!(encoding, T3dEncoding::Windows1252);
+        assert_eq!(objects[0].properties["Tag"], T3dPropertyValue::Value(T3dValue::String(String::from("Café €“"))));
+        assert_eq!(encoding::encode("Café €“ ✓", encoding), b"Caf\xe9 \x80\x93 ?");
+        let mut truncated = encoding::encode("Tag=\"Café\"", T3dEncoding::Utf16Le);
+        truncated.push(b'x');
+        assert_eq!(encoding::decode(truncated.as_slice()), (String::from("Tag=\"Café\"\u{FFFD}"), T3dEncoding::Utf16Le));
+        assert_eq!(encoding::decode(&truncated[2..]), (String::from("Tag=\"Café\"\u{FFFD}"), T3dEncoding::Utf16LeNoBom));
+        Ok(())
+    }
+
+    #[test]
+    fn lossless_round_trip() -> Result<(), String> {
+        let options = parser::T3dParseOptions { lossless: true, ..Default::default() };
+        for path in ["src/tests/data/DMDeathFan.t3d", "src/tests/data/MG_NTW2_Lib_Stairway.t3d", "src/tests/data/mirra.t3d", "src/tests/data/terraininfo.t3d", "src/tests/data/ue2_sparse_arrays.t3d", "src/tests/data/udk_static_mesh_actor.t3d", "src/tests/data/ue4_static_mesh_actor.t3d"] {
+            let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+            let objects = parser::parse_t3d_with_options(contents.as_str(), options).map_err(|e| e.to_string())?;
+            assert_eq!(writer::write_t3d(&objects), contents);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn lossless_duplicates_and_line_endings() {
+        let contents = "\r\nBegin Actor Class=Light Name=Light0\r\n\tLightBrightness=064.0\r\n\tTag=A\r\n\tTag=B\r\nEnd Actor\r\n\r\n";
+        let options = parser::T3dParseOptions { lossless: true, ..Default::default() };
+        let objects = parser::parse_t3d_with_options(contents, options).unwrap();
+        assert_eq!(writer::write_t3d(&objects), contents);
+
+        // Duplicates go away along with the property they duplicate.
+        let mut removed = objects.clone();
+        removed[0].remove_property("Tag");
+        assert_eq!(writer::write_t3d(&removed), "\r\nBegin Actor Class=Light Name=Light0\r\n\tLightBrightness=064.0\r\nEnd Actor\r\n\r\n");
+        let mut replaced = objects.clone();
+        replaced[0].set_property("Tag", T3dPropertyValue::Value(T3dValue::Identifier(String::from("C"))));
+        assert_eq!(writer::write_t3d(&replaced), "\r\nBegin Actor Class=Light Name=Light0\r\n\tLightBrightness=064.0\r\n\tTag=C\r\nEnd Actor\r\n\r\n");
+    }
+
+    #[test]
+    fn numeric_literals() {
+        let contents = "Begin Object\n   A=1.\n   B=-.5\n   C=1E-05\n   D=0x1F\n   E=4294967295\n   F=127.0.0.1\n   G=1st\nEnd Object\n";
+        let objects = parser::parse_t3d(contents).unwrap();
+        let value = |name: &str| match &objects[0].properties[name] {
+            T3dPropertyValue::Value(value) => value.clone(),
+            T3dPropertyValue::Array(_) => panic!("{} is an array", name),
+        };
+        assert_eq!(value("A"), T3dValue::Float(1.0));
+        assert_eq!(value("B"), T3dValue::Float(-0.5));
+        assert_eq!(value("C"), T3dValue::Float(1e-5));
+        assert_eq!(value("D"), T3dValue::Int(31));
+        assert_eq!(value("E"), T3dValue::Int(4294967295));
+        assert_eq!(value("F"), T3dValue::Identifier(String::from("127.0.0.1")));
+        assert_eq!(value("G"), T3dValue::Identifier(String::from("1st")));
+
+        let error = parser::parse_t3d("Begin Object\n   A=99999999999999999999\nEnd Object\n").unwrap_err();
+        assert_eq!(parser::error_line_column(&error), (2, 6));
+        assert!(parser::parse_t3d("Begin Object\n   A(4294967295)=1\nEnd Object\n").is_err());
+        for float in ["1e99", "-1.5E+40"] {
+            let error = parser::parse_t3d(format!("Begin Object\n   A={}\nEnd Object\n", float).as_str()).unwrap_err();
+            assert_eq!(parser::error_line_column(&error), (2, 6));
+        }
+    }
+
+    #[test]
+    fn string_escapes() {
+        let contents = "Begin Actor Class=LevelInfo Name=LevelInfo0\n   Title=\"The \\\"Best\\\" Map\"\n   Author=\"C:\\\\Maps\\q\"\n   Message=\"One\\nTwo\\tThree\"\nEnd Actor\n";
+        let options = parser::T3dParseOptions { lossless: true, dialect: Some(T3dDialect::Ue3) };
+        let objects = parser::parse_t3d_with_options(contents, options).unwrap();
+        let string = |value: &str| T3dPropertyValue::Value(T3dValue::String(String::from(value)));
+        assert_eq!(objects[0].properties["Title"], string("The \"Best\" Map"));
+        assert_eq!(objects[0].properties["Author"], string("C:\\Maps\\q"));
+        assert_eq!(objects[0].properties["Message"], string("One\nTwo\tThree"));
+        assert_eq!(writer::write_t3d(&objects), contents);
+        let mut formatted = objects.clone();
+        formatted[0].layout = None;
+        let written = writer::write_t3d_with_dialect(&formatted, T3dDialect::Ue3);
+        assert!(written.contains("Author=\"C:\\\\Maps\\\\q\""));
+        assert!(written.contains("Message=\"One\\nTwo\\tThree\""));
+        assert_eq!(parser::parse_t3d_with_options(written.as_str(), options).unwrap(), formatted);
+
+        // Escapes are decoded when the text is detected to be from UE3.
+        let udk = contents.replace("Name=LevelInfo0", "Name=LevelInfo0 ObjName=LevelInfo_0");
+        let objects = parser::parse_t3d(udk.as_str()).unwrap();
+        assert_eq!(objects[0].properties["Title"], string("The \"Best\" Map"));
+        assert_eq!(objects[0].properties["Author"], string("C:\\Maps\\q"));
+        assert!(writer::write_t3d(&objects).contains("Title=\"The \\\"Best\\\" Map\""));
+
+        // UE1 and UE2 strings may end with a backslash, which does not escape the closing quote.
+        let contents = "Begin Actor Class=LevelInfo Name=LevelInfo0\n   Path=\"C:\\Maps\\\"\n   Paths=(\"C:\\\",\"D:\\\")\nEnd Actor\n";
+        for dialect in [None, Some(T3dDialect::Ue2)] {
+            let objects = parser::parse_t3d_with_options(contents, parser::T3dParseOptions { dialect, ..Default::default() }).unwrap();
+            assert_eq!(objects[0].properties["Path"], string("C:\\Maps\\"));
+            let paths = T3dValue::Array(vec![Some(T3dValue::String(String::from("C:\\"))), Some(T3dValue::String(String::from("D:\\")))]);
+            assert_eq!(objects[0].properties["Paths"], T3dPropertyValue::Value(paths));
+            let written = writer::write_t3d_with_dialect(&objects, T3dDialect::Ue2);
+            assert!(written.contains("Path=\"C:\\Maps\\\""));
+            assert_eq!(parser::parse_t3d(written.as_str()).unwrap(), objects);
+        }
+    }
+
+    #[test]
+    fn lossless_edit() {
+        let contents = "Begin Actor Class=Light Name=Light0
+\tLightBrightness=064.0
+\tTag=A
+\tbHidden=False
+End Actor
+";
+        let options = parser::T3dParseOptions { lossless: true, ..Default::default() };
+        let mut objects = parser::parse_t3d_with_options(contents, options).unwrap();
+        let object = objects.first_mut().unwrap();
+        object.properties.insert(String::from("Tag"), T3dPropertyValue::Value(T3dValue::Identifier(String::from("B"))));
+        object.properties.remove("bHidden");
+        object.properties.insert(String::from("LightHue"), T3dPropertyValue::Value(T3dValue::Int(12)));
+        assert_eq!(writer::write_t3d(&objects), "Begin Actor Class=Light Name=Light0
+\tLightBrightness=064.0
+\tTag=B
+\tLightHue=12
+End Actor
+");
+
+        // New statements go before the `End` line, however it is spelled.
+        for end in ["END Actor", "end Actor"] {
+            let contents = format!("Begin Actor Class=Brush Name=B\n{}\n", end);
+            let mut objects = parser::parse_t3d_with_options(contents.as_str(), options).unwrap();
+            objects[0].set_property("Tag", T3dPropertyValue::Value(T3dValue::Identifier(String::from("X"))));
+            assert_eq!(writer::write_t3d(&objects), format!("Begin Actor Class=Brush Name=B\n   Tag=X\n{}\n", end));
+        }
+    }
+
+    #[test]
+    fn lossless_edit_children() {
+        let contents = "Begin Map
+Begin Actor Class=Light Name=Light0
+End Actor
+Begin Actor Class=Light Name=Light1
+    Tag=A
+End Actor
+    Title=\"Map\"
+Begin Actor Class=Light Name=Light2
+End Actor
+End Map
+";
+        let options = parser::T3dParseOptions { lossless: true, ..Default::default() };
+        let mut objects = parser::parse_t3d_with_options(contents, options).unwrap();
+        let map = objects.first_mut().unwrap();
+        assert_eq!(map.remove_child(0).name().as_deref(), Some("Light0"));
+        map.children[0].remove_property("Tag");
+        map.set_property("Title", T3dPropertyValue::Value(T3dValue::String(String::from("Edited"))));
+        assert_eq!(map.property_span("Title", None), None);
+        let mut light = T3dObject::new("Actor");
+        light.header.push((String::from("Class"), T3dValue::Identifier(String::from("Light"))));
+        map.children.push(light);
+        assert_eq!(writer::write_t3d(&objects), "Begin Map
+Begin Actor Class=Light Name=Light1
+End Actor
+    Title=\"Edited\"
+Begin Actor Class=Light Name=Light2
+End Actor
+    Begin Actor Class=Light
+    End Actor
+End Map
+");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_round_trip() -> Result<(), String> {
+        let objects = parse_file("src/tests/data/mirra.t3d".to_string())?;
+        let json = json::to_json(&objects).map_err(|e| e.to_string())?;
+        assert_eq!(json::from_json(json.as_str()).map_err(|e| e.to_string())?, objects);
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_schema() {
+        let objects = parser::parse_t3d("Begin Actor Class=Brush
+   Tag=\"Brush\"
+   Group=Brush
+   Brush=Model'MyLevel.Brush'
+   Location=(Y=1.5,X=2)
+   Skins(1)=None
+End Actor").unwrap();
+        assert_eq!(json::to_json(&objects).unwrap(), concat!(
+            r#"[{"type":"Actor","header":[["Class",{"type":"Identifier","value":"Brush"}]],"children":[],"properties":{"#,
+            r#""Brush":{"type":"Value","value":{"type":"Reference","value":{"type":"Model","path":"MyLevel.Brush"}}},"#,
+            r#""Group":{"type":"Value","value":{"type":"Identifier","value":"Brush"}},"#,
+            r#""Location":{"type":"Value","value":{"type":"Struct","value":{"X":{"type":"Int","value":2},"Y":{"type":"Float","value":1.5}}}},"#,
+            r#""Skins":{"type":"Array","value":[[1,{"type":"Identifier","value":"None"}]]},"#,
+            r#""Tag":{"type":"Value","value":{"type":"String","value":"Brush"}}},"vector_properties":[]}]"#,
+        ));
+    }
+
     #[test]
     fn mirra_test() -> Result<(), String> {
         match parse_file("src/tests/data/mirra.t3d".to_string()) {
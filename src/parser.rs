@@ -1,17 +1,117 @@
+#![allow(clippy::result_large_err)]
+
 extern crate pest;
 
 use std::collections::HashMap;
-use std::fmt::{Debug};
+use std::fmt::{Debug, Display, Formatter};
 use pest_consume::Parser;
 use pest_consume::{Error, match_nodes};
+use std::ops::Range;
+use std::path::Path;
+use pest::error::{ErrorVariant, InputLocation, LineColLocation};
 use crate::ast::*;
+use crate::dialect::T3dDialect;
+use crate::encoding::{decode, T3dEncoding};
 
 #[derive(Parser)]
 #[grammar = "t3d.pest"]
 pub struct T3dParser;
 
 type Result<T> = std::result::Result<T, Error<Rule>>;
-type Node<'i> = pest_consume::Node<'i, Rule, ()>;
+type Node<'i> = pest_consume::Node<'i, Rule, &'i T3dParseContext<'i>>;
+
+/// Options that control how T3D text is parsed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct T3dParseOptions {
+    /// Record the layout of every object so that it can be written back byte-for-byte.
+    pub lossless: bool,
+    /// Read the text as this dialect. UE1 and UE2 strings are read verbatim instead of decoding backslash escapes.
+    /// When `None`, the dialect is detected with [`T3dDialect::detect`], and escapes are only decoded if it is UE3
+    /// or later.
+    pub dialect: Option<T3dDialect>,
+}
+
+/// Maps byte offsets to line and column numbers.
+struct LineIndex {
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    fn new(contents: &str) -> Self {
+        let mut line_starts = vec![0];
+        line_starts.extend(contents.match_indices('\n').map(|(i, _)| i + 1));
+        LineIndex { line_starts }
+    }
+
+    /// Returns the 1-based line and column of a byte offset.
+    fn line_column(&self, contents: &str, offset: usize) -> (usize, usize) {
+        let line = self.line_starts.partition_point(|start| *start <= offset);
+        let line_start = self.line_starts[line - 1];
+        (line, contents[line_start..offset].chars().count() + 1)
+    }
+}
+
+/// State that is shared by every node of a parse.
+struct T3dParseContext<'a> {
+    options: T3dParseOptions,
+    contents: &'a str,
+    line_index: LineIndex,
+}
+
+impl T3dParseContext<'_> {
+    fn span(&self, start: usize, end: usize) -> T3dSpan {
+        let (start_line, start_column) = self.line_index.line_column(self.contents, start);
+        let (end_line, end_column) = self.line_index.line_column(self.contents, end);
+        T3dSpan { start, end, start_line, start_column, end_line, end_column }
+    }
+}
+
+/// A parsed value along with the byte offsets of its source text.
+struct Located<T> {
+    value: T,
+    start: usize,
+    end: usize,
+}
+
+/// Returns the length of the `Begin <Type>` prefix of an object's source text.
+fn begin_length(text: &str) -> usize {
+    let after_begin = text["Begin".len()..].trim_start();
+    let type_length = after_begin.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(after_begin.len());
+    text.len() - after_begin.len() + type_length
+}
+
+/// Returns the length of the `End <Type>` suffix of an object's source text.
+fn end_length(text: &str) -> usize {
+    let before_type = text.trim_end_matches(|c: char| c.is_ascii_alphanumeric() || c == '_');
+    let before_end = before_type.trim_end();
+    text.len() - before_end.len() + "End".len()
+}
+
+/// Adds a property assignment to a property map, returning `false` if it was a duplicate that was not stored.
+fn add_property(properties: &mut HashMap<String, T3dPropertyValue>, p: T3dPropertyAssignment) -> bool {
+    if let Some(value) = properties.get_mut(&p.name) {
+        // Property has already been encountered.
+        match value {
+            T3dPropertyValue::Array(a) => {
+                a.push((p.index, p.value));
+                true
+            },
+            _ => {
+                // A duplicate assignment to a property that is not an array, which UnrealEd ignores.
+                false
+            }
+        }
+    } else {
+        // New property encountered.
+        if p.index.is_some() {
+            // Property is an array, so add a new array type.
+            properties.insert(p.name, T3dPropertyValue::Array(vec![(p.index, p.value)]));
+        } else {
+            properties.insert(p.name, T3dPropertyValue::Value(p.value));
+        }
+        true
+    }
+}
 
 macro_rules! match_nodes_any {
     ($nodes:expr; $($f:ident($v:ident) => $e:expr),*) => (
@@ -36,79 +136,167 @@ impl T3dParser {
         return Ok(String::from(input.as_str()))
     }
 
-    fn object_statements(input: Node) -> Result<Vec<T3dObjectStatement>> {
+    fn object_statements(input: Node) -> Result<Vec<Located<T3dObjectStatement>>> {
         let mut statements = Vec::new();
-        match_nodes_any!(input.into_children();
-            object_statement(s) => statements.push(s)
-        );
+        for node in input.into_children() {
+            let span = node.as_span();
+            statements.push(Located {
+                value: Self::object_statement(node)?,
+                start: span.start(),
+                end: span.end(),
+            });
+        }
         Ok(statements)
     }
 
     fn object(input: Node) -> Result<T3dObject> {
+        let context = *input.user_data();
+        let options = context.options;
+        let span = input.as_span();
+        let source = span.get_input();
         let mut children = Vec::new();
         let mut properties = HashMap::new();
         let mut vector_properties = Vec::new();
-        match_nodes!(input.into_children();
-            [id(i), object_statements(statements), id(_)] => {
-                for statement in statements {
-                    match statement {
-                        T3dObjectStatement::Object(o) => {
-                            children.push(Box::new(o));
-                        },
-                        T3dObjectStatement::PropertyAssignment(p) => {
-                            if let Some(value) = properties.get_mut(&p.name) {
-                                // Property has already been encountered.
-                                match value {
-                                    T3dPropertyValue::Array(a) => {
-                                        a.push((p.index, p.value))
-                                    },
-                                    _ => {
-                                        // TODO: handle this gracefully, for now just ignore
-                                        //return Err(format!("Un-indexed property assignment encountered for array type {}", &p.name))
-                                    }
-                                }
-                            } else {
-                                // New property encountered.
-                                if let Some(_) = p.index {
-                                    // Property is an array, so add a new array type.
-                                    properties.insert(p.name, T3dPropertyValue::Array(vec![(p.index, p.value)]));
-                                } else {
-                                    properties.insert(p.name, T3dPropertyValue::Value(p.value));
-                                }
-                            }
-                        },
-                        T3dObjectStatement::PropertyAssignmentVector(p) => {
-                            vector_properties.push((p.name, p.value))
-                        }
+        let mut property_spans: HashMap<String, Vec<T3dSpan>> = HashMap::new();
+        let mut vector_property_spans = Vec::new();
+        let mut custom_properties = Vec::new();
+        let mut layout = T3dLayout::default();
+        let (type_, header, statements) = match_nodes!(input.into_children();
+            [id(i), object_header(h), object_statements(s), id(_)] => (i, Some(h), s),
+            [id(i), object_header(h), id(_)] => (i, Some(h), Vec::new()),
+            [id(i), object_statements(s), id(_)] => (i, None, s),
+            [id(i), id(_)] => (i, None, Vec::new())
+        );
+        let mut position = match &header {
+            Some(header) => header.end,
+            None => span.start() + begin_length(span.as_str()),
+        };
+        layout.begin = String::from(&source[span.start()..position]);
+        let begin_span = context.span(span.start(), position);
+        let end_span = context.span(span.end() - end_length(span.as_str()), span.end());
+        let header = header.map(|h| h.value).unwrap_or_default();
+        if options.lossless {
+            layout.header = header.clone();
+        }
+        for located in statements {
+            let (leading, raw) = if options.lossless {
+                (String::from(&source[position..located.start]), String::from(&source[located.start..located.end]))
+            } else {
+                (String::new(), String::new())
+            };
+            position = located.end;
+            match located.value {
+                T3dObjectStatement::Object(mut o) => {
+                    if let Some(child_layout) = o.layout.as_mut() {
+                        child_layout.leading = leading;
                     }
+                    layout.statements.push(T3dLayoutStatement::Object(children.len()));
+                    children.push(*o);
+                },
+                T3dObjectStatement::PropertyAssignment(p) => {
+                    let statement = if options.lossless {
+                        Some(T3dLayoutStatement::Property {
+                            leading: leading.clone(),
+                            raw: raw.clone(),
+                            name: p.name.clone(),
+                            index: p.index,
+                            value: p.value.clone(),
+                        })
+                    } else {
+                        None
+                    };
+                    let name = p.name.clone();
+                    if add_property(&mut properties, p) {
+                        property_spans.entry(name).or_default().push(context.span(located.start, located.end));
+                        layout.statements.extend(statement);
+                    } else if options.lossless {
+                        // Duplicate assignments are dropped from the properties, but kept in the layout.
+                        layout.statements.push(T3dLayoutStatement::Duplicate { leading, raw, name });
+                    }
+                },
+                T3dObjectStatement::PropertyAssignmentVector(p) => {
+                    if options.lossless {
+                        layout.statements.push(T3dLayoutStatement::VectorProperty {
+                            leading,
+                            raw,
+                            name: p.name.clone(),
+                            value: p.value.clone(),
+                        });
+                    }
+                    vector_property_spans.push(context.span(located.start, located.end));
+                    vector_properties.push((p.name, p.value))
+                },
+                T3dObjectStatement::CustomProperties(value) => {
+                    if options.lossless {
+                        layout.statements.push(T3dLayoutStatement::CustomProperties { leading, raw, value: value.clone() });
+                    }
+                    custom_properties.push(value);
                 }
-                Ok(T3dObject {
-                    type_: i,
-                    children,
-                    properties,
-                    vector_properties
-                })
-            },
-            [id(i), id(_)] => {
-                Ok(T3dObject {
-                    type_: i,
-                    children,
-                    properties,
-                    vector_properties
-                })
             }
+        }
+        layout.end = String::from(&source[position..span.end()]);
+        Ok(T3dObject {
+            type_,
+            header,
+            children,
+            properties,
+            vector_properties,
+            custom_properties,
+            span: Some(context.span(span.start(), span.end())),
+            begin_span: Some(begin_span),
+            end_span: Some(end_span),
+            property_spans,
+            vector_property_spans,
+            layout: options.lossless.then_some(layout),
+            #[cfg(feature = "python")]
+            py_links: Default::default(),
+        })
+    }
+
+    fn object_header(input: Node) -> Result<Located<Vec<(String, T3dValue)>>> {
+        let span = input.as_span();
+        let mut attributes = Vec::new();
+        match_nodes_any!(input.into_children();
+            header_attribute(a) => attributes.push(a)
+        );
+        Ok(Located {
+            value: attributes,
+            start: span.start(),
+            end: span.end(),
+        })
+    }
+
+    fn header_attribute(input: Node) -> Result<(String, T3dValue)> {
+        match_nodes!(input.into_children();
+            [id(name), value(v)] => Ok((name, v)),
+            [id(name)] => Ok((name, T3dValue::String(String::new())))
         )
     }
 
     fn object_statement(input: Node) -> Result<T3dObjectStatement> {
         match_nodes!(input.into_children();
-            [object(o)] => Ok(T3dObjectStatement::Object(o)),
+            [object(o)] => Ok(T3dObjectStatement::Object(Box::new(o))),
             [property_assignment(p)] => Ok(T3dObjectStatement::PropertyAssignment(p)),
             [property_assignment_vector(p)] => Ok(T3dObjectStatement::PropertyAssignmentVector(p)),
-            [property_assignment_inline_struct(p)] => Ok(T3dObjectStatement::PropertyAssignment(p))
+            [property_assignment_inline_struct(p)] => Ok(T3dObjectStatement::PropertyAssignment(p)),
+            [custom_properties(c)] => Ok(T3dObjectStatement::CustomProperties(c))
         )
     }
 
+    fn custom_properties(input: Node) -> Result<String> {
+        match_nodes!(input.into_children();
+            [custom_properties_text(text)] => Ok(text)
+        )
+    }
+
+    fn custom_properties_text(input: Node) -> Result<String> {
+        Ok(String::from(input.as_str()))
+    }
+
+    fn reference_type(input: Node) -> Result<String> {
+        Ok(String::from(input.as_str()))
+    }
+
     fn property_assignment(input: Node) -> Result<T3dPropertyAssignment> {
         // An empty string will be written like this:
         // Foo=
@@ -121,7 +309,7 @@ impl T3dParser {
                 value: v,
                 index: None
             }),
-            [id(name), int(index), value(v)] => Ok(T3dPropertyAssignment {
+            [id(name), array_index(index), value(v)] => Ok(T3dPropertyAssignment {
                 name,
                 value: v,
                 index: Some(index)
@@ -131,7 +319,7 @@ impl T3dParser {
                 value: T3dValue::String(String::new()),
                 index: None
             }),
-            [id(name), int(index)] => Ok(T3dPropertyAssignment {
+            [id(name), array_index(index)] => Ok(T3dPropertyAssignment {
                 name,
                 value: T3dValue::String(String::new()),
                 index: Some(index)
@@ -171,16 +359,46 @@ impl T3dParser {
         })
     }
 
-    fn int(input: Node) -> Result<i32> {
-        Ok(input.as_str().parse::<i32>().unwrap())
+    fn int(input: Node) -> Result<i64> {
+        let text = input.as_str();
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+        let value = match digits.get(..2) {
+            Some(prefix) if prefix.eq_ignore_ascii_case("0x") => i64::from_str_radix(&digits[2..], 16),
+            _ => digits.parse::<i64>(),
+        };
+        match value {
+            Ok(value) if negative => Ok(-value),
+            Ok(value) => Ok(value),
+            Err(err) => Err(input.error(format!("invalid integer: {}", err))),
+        }
     }
 
     fn float(input: Node) -> Result<f32> {
-        Ok(input.as_str().parse::<f32>().unwrap())
+        match input.as_str().parse::<f32>() {
+            Ok(value) if value.is_finite() => Ok(value),
+            Ok(_) => Err(input.error("invalid float: number too large to fit in target type")),
+            Err(err) => Err(input.error(format!("invalid float: {}", err))),
+        }
+    }
+
+    fn array_index(input: Node) -> Result<i32> {
+        let index = match_nodes!(input.children(); [int(index)] => index);
+        i32::try_from(index).map_err(|_| input.error("array index out of range"))
     }
 
     fn string(input: Node) -> Result<String> {
-        Ok(input.as_str().to_string())
+        match input.user_data().options.dialect {
+            Some(dialect) if dialect.escapes_strings() => Ok(unescape_string(input.as_str())),
+            // Without a dialect, escapes are decoded once the dialect has been detected.
+            _ => Ok(String::from(input.as_str())),
+        }
+    }
+
+    fn verbatim_string(input: Node) -> Result<String> {
+        Ok(String::from(input.as_str()))
     }
 
     fn struct_(input: Node) -> Result<Box<T3dStruct>> {
@@ -197,16 +415,30 @@ impl T3dParser {
         Ok(String::from(input.as_str()))
     }
 
+    fn quoted_reference_path(input: Node) -> Result<String> {
+        Ok(String::from(input.as_str()))
+    }
+
     fn reference(input: Node) -> Result<T3dReference> {
         match_nodes!(input.into_children();
-            [id(type_), reference_path(path)] => Ok(T3dReference { type_, path })
+            [reference_type(type_), reference_path(path)] => Ok(T3dReference { type_, path, quoted: false }),
+            [reference_type(type_), quoted_reference_path(path)] => Ok(T3dReference { type_, path, quoted: true })
         )
     }
 
+    fn identifier(input: Node) -> Result<String> {
+        Ok(String::from(input.as_str()))
+    }
+
+    fn empty(input: Node) -> Result<()> {
+        Ok(())
+    }
+
     fn array(input: Node) -> Result<Vec<Option<T3dValue>>> {
         let mut values: Vec<Option<T3dValue>> = Vec::new();
         match_nodes_any!(input.into_children();
-            value(v) => values.push(Some(v))
+            value(v) => values.push(Some(v)),
+            empty(_e) => values.push(None)
         );
         Ok(values)
     }
@@ -218,11 +450,12 @@ impl T3dParser {
             [int(i)] => Ok(T3dValue::Int(i)),
             [float(f)] => Ok(T3dValue::Float(f)),
             [string(s)] => Ok(T3dValue::String(s)),
+            [verbatim_string(s)] => Ok(T3dValue::String(s)),
             [struct_(s)] => Ok(T3dValue::Struct(s)),
             [reference(r)] => Ok(T3dValue::Reference(r)),
-            [reference_path(r)] => {
+            [identifier(r)] => {
                 if str.eq_ignore_ascii_case("true") || str.eq_ignore_ascii_case("false") {
-                    Ok(T3dValue::Bool(str.to_lowercase().parse::<bool>().unwrap()))
+                    Ok(T3dValue::Bool(str.eq_ignore_ascii_case("true")))
                 } else {
                     Ok(T3dValue::Identifier(r))
                 }
@@ -235,20 +468,286 @@ impl T3dParser {
     }
 
     fn t3d(input: Node) -> Result<Vec<T3dObject>> {
-        let mut objects = Vec::new();
-        match_nodes_any!(input.into_children();
-            object(o) => objects.push(o),
-            EOI(_e) => {}
-        );
+        let source = input.as_str();
+        let mut objects: Vec<T3dObject> = Vec::new();
+        let mut position = 0;
+        for node in input.into_children() {
+            let span = node.as_span();
+            let leading = &source[position..span.start()];
+            position = span.end();
+            match node.as_rule() {
+                Rule::object => {
+                    let mut object = Self::object(node)?;
+                    if let Some(layout) = object.layout.as_mut() {
+                        layout.leading = String::from(leading);
+                    }
+                    objects.push(object)
+                },
+                Rule::EOI => {
+                    if let Some(layout) = objects.last_mut().and_then(|o| o.layout.as_mut()) {
+                        layout.trailing = String::from(leading);
+                    }
+                },
+                _ => { return Err(node.error("unexpected rule")) }
+            }
+        }
         Ok(objects)
     }
 }
 
 pub type T3dSyntaxError = Error<Rule>;
 
+/// Returns the 1-based line and column where a syntax error occurred.
+pub fn error_line_column(error: &T3dSyntaxError) -> (usize, usize) {
+    match error.line_col {
+        LineColLocation::Pos(position) => position,
+        LineColLocation::Span(start, _) => start,
+    }
+}
+
+/// Returns the source line where a syntax error occurred, without its line ending. Unlike `line()`, whitespace is
+/// not replaced with visible symbols when the error is at the end of the line.
+pub fn error_line_text<'a>(error: &T3dSyntaxError, contents: &'a str) -> &'a str {
+    let (line, _) = error_line_column(error);
+    contents.lines().nth(line.saturating_sub(1)).unwrap_or_default()
+}
+
+/// Returns the names of the grammar rules that were expected where a syntax error occurred.
+pub fn error_expected_rules(error: &T3dSyntaxError) -> Vec<String> {
+    match &error.variant {
+        ErrorVariant::ParsingError { positives, .. } => {
+            positives.iter().map(|rule| format!("{:?}", rule)).collect()
+        },
+        ErrorVariant::CustomError { .. } => Vec::new(),
+    }
+}
+
+/// Returns the byte offset where a syntax error occurred.
+fn error_offset(error: &T3dSyntaxError) -> usize {
+    match error.location {
+        InputLocation::Pos(position) => position,
+        InputLocation::Span((start, _)) => start,
+    }
+}
+
+/// Finds the byte ranges of `Begin Actor`/`End Actor` blocks and of top-level objects by scanning the `Begin` and
+/// `End` lines, so that they can be found even if the text between them is malformed. Like the grammar, keywords
+/// are matched ignoring case and may be separated by any whitespace. An actor that is missing its `End Actor` line
+/// ends where the next actor, or its enclosing object, begins.
+fn find_blocks(contents: &str) -> (Vec<Range<usize>>, Vec<Range<usize>>) {
+    let mut actors = Vec::new();
+    let mut top_level = Vec::new();
+    let mut depth = 0usize;
+    let mut top_level_start = None;
+    // Start offset of the current actor and the depth of its `Begin Actor` line.
+    let mut actor: Option<(usize, usize)> = None;
+    let mut line_start = 0;
+    // Implicitly closed actors end at the last non-whitespace character before `offset`.
+    let trimmed_end = |start: usize, offset: usize| start + contents[start..offset].trim_end().len();
+    for line in contents.split_inclusive('\n') {
+        let trimmed = line.trim();
+        let start = line_start + (line.len() - line.trim_start().len());
+        let end = start + trimmed.len();
+        line_start += line.len();
+        let mut words = trimmed.split_whitespace();
+        let (keyword, type_) = (words.next().unwrap_or_default(), words.next());
+        let is_actor = type_.is_some_and(|type_| type_.eq_ignore_ascii_case("Actor"));
+        if type_.is_some() && keyword.eq_ignore_ascii_case("Begin") {
+            if is_actor {
+                if let Some((actor_start, actor_depth)) = actor.take() {
+                    actors.push(actor_start..trimmed_end(actor_start, start));
+                    depth = actor_depth;
+                }
+                actor = Some((start, depth));
+            }
+            if depth == 0 {
+                top_level_start = Some(start);
+            }
+            depth += 1;
+        } else if type_.is_some() && keyword.eq_ignore_ascii_case("End") {
+            if let Some((actor_start, actor_depth)) = actor {
+                if is_actor {
+                    actors.push(actor_start..end);
+                    actor = None;
+                    depth = actor_depth + 1;
+                } else if depth <= actor_depth + 1 {
+                    // The enclosing object ends before the actor did.
+                    actors.push(actor_start..trimmed_end(actor_start, start));
+                    actor = None;
+                    depth = actor_depth;
+                }
+            }
+            depth = depth.saturating_sub(1);
+            if depth == 0 {
+                if let Some(top_level_start) = top_level_start.take() {
+                    top_level.push(top_level_start..end);
+                }
+            }
+        }
+    }
+    if let Some((actor_start, _)) = actor {
+        actors.push(actor_start..trimmed_end(actor_start, contents.len()));
+    }
+    if let Some(top_level_start) = top_level_start {
+        top_level.push(top_level_start..contents.len());
+    }
+    (actors, top_level)
+}
+
+/// Replaces a range of text with spaces, keeping line breaks and byte offsets intact.
+fn blank(text: &mut String, range: Range<usize>) {
+    let blanked: String = text[range.clone()].chars()
+        .map(|c| if c == '\n' || c == '\r' { c.to_string() } else { " ".repeat(c.len_utf8()) })
+        .collect();
+    text.replace_range(range, blanked.as_str());
+}
+
+/// Parses T3D text, skipping malformed objects instead of failing.
+///
+/// Each malformed actor is skipped on its own, resynchronising at the next `Begin Actor`/`End Actor` boundary. If
+/// the text is still malformed outside of the actors, the top-level object containing the error is skipped. Returns
+/// every object that could be parsed, along with a diagnostic for every region that was skipped. Spans of the
+/// returned objects refer to the original text.
+pub fn parse_t3d_recovering(contents: &str, options: T3dParseOptions) -> (Vec<T3dObject>, Vec<T3dDiagnostic>) {
+    if let Ok(objects) = parse_t3d_with_options(contents, options) {
+        return (objects, Vec::new())
+    }
+    let line_index = LineIndex::new(contents);
+    let context = T3dParseContext { options, contents, line_index };
+    let diagnostic = |range: &Range<usize>, error: &T3dSyntaxError, offset: usize| {
+        let begin_line = contents[range.clone()].lines().next().unwrap_or_default().trim();
+        let (line, column) = context.line_index.line_column(contents, offset);
+        T3dDiagnostic {
+            span: context.span(range.start, range.end),
+            message: format!("Skipped `{}`: {} at line {}, column {}", begin_line, error.variant.message(), line, column),
+        }
+    };
+    let mut diagnostics = Vec::new();
+    let mut text = String::from(contents);
+    let (actors, top_level) = find_blocks(contents);
+    for range in &actors {
+        if let Err(error) = parse_t3d_with_options(&contents[range.clone()], options) {
+            diagnostics.push(diagnostic(range, &error, range.start + error_offset(&error)));
+            blank(&mut text, range.clone());
+        }
+    }
+    loop {
+        match parse_t3d_with_options(text.as_str(), options) {
+            Ok(objects) => {
+                diagnostics.sort_by_key(|d| d.span.start);
+                return (objects, diagnostics)
+            },
+            Err(error) => {
+                let offset = error_offset(&error);
+                let range = actors.iter().chain(top_level.iter())
+                    .find(|range| range.contains(&offset) && !text[(*range).clone()].trim().is_empty())
+                    .cloned()
+                    .unwrap_or(0..contents.len());
+                diagnostics.push(diagnostic(&range, &error, offset));
+                if range == (0..contents.len()) {
+                    // Nothing left that could be skipped on its own.
+                    diagnostics.sort_by_key(|d| d.span.start);
+                    return (Vec::new(), diagnostics)
+                }
+                blank(&mut text, range);
+            }
+        }
+    }
+}
+
 pub fn parse_t3d(contents: &str) -> std::result::Result<Vec<T3dObject>, T3dSyntaxError> {
-    match T3dParser::t3d(T3dParser::parse(Rule::t3d, contents)?.single()?) {
-        Ok(t3d) => { Ok(t3d) },
-        Err(error) => Err(error)
+    parse_t3d_with_options(contents, T3dParseOptions::default())
+}
+
+pub fn parse_t3d_with_options(contents: &str, options: T3dParseOptions) -> std::result::Result<Vec<T3dObject>, T3dSyntaxError> {
+    let context = T3dParseContext {
+        options,
+        contents,
+        line_index: LineIndex::new(contents),
+    };
+    let mut objects = T3dParser::t3d(T3dParser::parse_with_userdata(Rule::t3d, contents, &context)?.single()?)?;
+    if options.dialect.is_none() && T3dDialect::detect(&objects).is_some_and(|dialect| dialect.escapes_strings()) {
+        objects.iter_mut().for_each(unescape_strings);
+    }
+    Ok(objects)
+}
+
+/// Decodes the escapes of every string of an object that was parsed verbatim, including those in its layout.
+fn unescape_strings(object: &mut T3dObject) {
+    fn unescape_value(value: &mut T3dValue) {
+        match value {
+            T3dValue::String(string) => *string = unescape_string(string),
+            T3dValue::Struct(values) | T3dValue::InlineStruct(values) => values.values_mut().for_each(unescape_value),
+            T3dValue::Array(values) => values.iter_mut().flatten().for_each(unescape_value),
+            _ => {},
+        }
+    }
+    object.header.iter_mut().for_each(|(_, value)| unescape_value(value));
+    for value in object.properties.values_mut() {
+        match value {
+            T3dPropertyValue::Value(value) => unescape_value(value),
+            T3dPropertyValue::Array(values) => values.iter_mut().for_each(|(_, value)| unescape_value(value)),
+        }
+    }
+    if let Some(layout) = &mut object.layout {
+        layout.header.iter_mut().for_each(|(_, value)| unescape_value(value));
+        for statement in &mut layout.statements {
+            if let T3dLayoutStatement::Property { value, .. } = statement {
+                unescape_value(value);
+            }
+        }
+    }
+    object.children.iter_mut().for_each(unescape_strings);
+}
+
+/// Detects the encoding of T3D bytes, decodes and parses them. Spans refer to the decoded text.
+pub fn parse_t3d_bytes(bytes: &[u8]) -> std::result::Result<(Vec<T3dObject>, T3dEncoding), T3dSyntaxError> {
+    parse_t3d_bytes_with_options(bytes, T3dParseOptions::default())
+}
+
+pub fn parse_t3d_bytes_with_options(bytes: &[u8], options: T3dParseOptions) -> std::result::Result<(Vec<T3dObject>, T3dEncoding), T3dSyntaxError> {
+    let (contents, encoding) = decode(bytes);
+    Ok((parse_t3d_with_options(contents.as_str(), options)?, encoding))
+}
+
+/// An error that occurred while reading a T3D file.
+#[derive(Debug)]
+pub enum T3dReadError {
+    Io(std::io::Error),
+    Syntax(Box<T3dSyntaxError>),
+}
+
+impl Display for T3dReadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            T3dReadError::Io(err) => write!(f, "{}", err),
+            T3dReadError::Syntax(err) => write!(f, "{}", err),
+        }
     }
 }
+
+impl std::error::Error for T3dReadError {}
+
+impl From<std::io::Error> for T3dReadError {
+    fn from(err: std::io::Error) -> Self {
+        T3dReadError::Io(err)
+    }
+}
+
+impl From<T3dSyntaxError> for T3dReadError {
+    fn from(err: T3dSyntaxError) -> Self {
+        T3dReadError::Syntax(Box::new(err))
+    }
+}
+
+/// Reads and parses a T3D file, detecting its encoding. Syntax errors carry the path of the file.
+pub fn read_t3d_file(path: impl AsRef<Path>) -> std::result::Result<(Vec<T3dObject>, T3dEncoding), T3dReadError> {
+    read_t3d_file_with_options(path, T3dParseOptions::default())
+}
+
+pub fn read_t3d_file_with_options(path: impl AsRef<Path>, options: T3dParseOptions) -> std::result::Result<(Vec<T3dObject>, T3dEncoding), T3dReadError> {
+    let path = path.as_ref();
+    let bytes = std::fs::read(path)?;
+    parse_t3d_bytes_with_options(bytes.as_slice(), options)
+        .map_err(|err| T3dReadError::from(err.with_path(path.to_string_lossy().as_ref())))
+}
@@ -0,0 +1,169 @@
+//! A typed view of `Begin Polygon` objects.
+
+use crate::ast::{struct_member, T3dObject, T3dPropertyValue, T3dSpan, T3dValue, T3dViewError};
+use crate::dialect::T3dDialect;
+use crate::flags::{PolyFlags, PolyFlagsLayout};
+
+pub type T3dVector = [f32; 3];
+
+/// A polygon of a brush, read from a `Begin Polygon` object.
+///
+/// Vectors that are missing from the object default to zero, except for the normal, which is computed from the
+/// vertices.
+#[cfg_attr(feature = "python", pyo3::pyclass)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct T3dPolygon {
+    pub origin: T3dVector,
+    pub normal: T3dVector,
+    pub texture_u: T3dVector,
+    pub texture_v: T3dVector,
+    pub vertices: Vec<T3dVector>,
+    pub pan_u: i32,
+    pub pan_v: i32,
+    pub texture: Option<String>,
+    /// The `Flags` bits. Negative values in the source text are reinterpreted as unsigned.
+    pub flags: PolyFlags,
+    /// The layout that `flags` were read with, which gives the names of the bits.
+    pub flags_layout: PolyFlagsLayout,
+    pub item: Option<String>,
+    pub link: Option<i32>,
+}
+
+/// Computes the unit normal of a polygon with Newell's method, which also handles slightly non-planar polygons.
+pub fn polygon_normal(vertices: &[T3dVector]) -> T3dVector {
+    let mut normal = [0.0f32; 3];
+    for (i, a) in vertices.iter().enumerate() {
+        let b = vertices[(i + 1) % vertices.len()];
+        normal[0] += (a[1] - b[1]) * (a[2] + b[2]);
+        normal[1] += (a[2] - b[2]) * (a[0] + b[0]);
+        normal[2] += (a[0] - b[0]) * (a[1] + b[1]);
+    }
+    let length = normal.iter().map(|c| c * c).sum::<f32>().sqrt();
+    if length > 0.0 {
+        normal.map(|c| c / length)
+    } else {
+        normal
+    }
+}
+
+fn vector(name: &str, values: &[f32], span: Option<T3dSpan>) -> Result<T3dVector, T3dViewError> {
+    <T3dVector>::try_from(values).map_err(|_| T3dViewError {
+        message: format!("{} must have 3 components, found {}", name, values.len()),
+        span,
+    })
+}
+
+fn header_int(object: &T3dObject, name: &str) -> Result<Option<i64>, T3dViewError> {
+    match object.header_value(name) {
+        None => Ok(None),
+        Some(T3dValue::Int(value)) => Ok(Some(*value)),
+        Some(value) => Err(T3dViewError {
+            message: format!("{} must be an integer, found {}", name, value),
+            span: object.begin_span,
+        }),
+    }
+}
+
+fn pan(object: &T3dObject) -> Result<(i32, i32), T3dViewError> {
+    let Some(T3dPropertyValue::Value(value)) = object.property("Pan") else {
+        return Ok((0, 0))
+    };
+    let span = object.property_span("Pan", None);
+    let (T3dValue::InlineStruct(pan) | T3dValue::Struct(pan)) = value else {
+        return Err(T3dViewError { message: format!("Pan must be a struct, found {}", value), span })
+    };
+    let component = |name: &str| match struct_member(pan, name) {
+        None => Ok(0),
+        Some(T3dValue::Int(value)) => i32::try_from(*value).map_err(|_| format!("Pan {} is out of range", name)),
+        Some(T3dValue::Float(value)) => Ok(value.round() as i32),
+        Some(value) => Err(format!("Pan {} must be a number, found {}", name, value)),
+    };
+    match (component("U"), component("V")) {
+        (Ok(u), Ok(v)) => Ok((u, v)),
+        (Err(message), _) | (_, Err(message)) => Err(T3dViewError { message, span }),
+    }
+}
+
+impl T3dPolygon {
+    /// Reads a polygon from a `Begin Polygon` object, checking that its vectors have exactly three components and
+    /// that there are at least three vertices.
+    pub fn from_object(object: &T3dObject) -> Result<Self, T3dViewError> {
+        T3dPolygon::from_object_with_layout(object, PolyFlagsLayout::default())
+    }
+
+    /// Reads a polygon with the flags layout of a dialect.
+    pub fn from_object_with_dialect(object: &T3dObject, dialect: T3dDialect) -> Result<Self, T3dViewError> {
+        T3dPolygon::from_object_with_layout(object, dialect.poly_flags_layout())
+    }
+
+    /// Reads a polygon whose `Flags` use the given layout.
+    pub fn from_object_with_layout(object: &T3dObject, flags_layout: PolyFlagsLayout) -> Result<Self, T3dViewError> {
+        if !object.type_.eq_ignore_ascii_case("Polygon") {
+            return Err(T3dViewError {
+                message: format!("Expected a Polygon object, found {}", object.type_),
+                span: object.begin_span,
+            })
+        }
+        let mut origin = None;
+        let mut normal = None;
+        let mut texture_u = None;
+        let mut texture_v = None;
+        let mut vertices = Vec::new();
+        for (i, (name, values)) in object.vector_properties.iter().enumerate() {
+            let span = object.vector_property_spans.get(i).copied();
+            // Other vectors, such as the four-component `SurfaceColor` of some engines, are ignored.
+            let target = match name.to_ascii_lowercase().as_str() {
+                "origin" => &mut origin,
+                "normal" => &mut normal,
+                "textureu" => &mut texture_u,
+                "texturev" => &mut texture_v,
+                "vertex" => {
+                    vertices.push(vector(name, values, span)?);
+                    continue
+                },
+                _ => continue,
+            };
+            *target = Some(vector(name, values, span)?);
+        }
+        if vertices.len() < 3 {
+            return Err(T3dViewError {
+                message: format!("Polygon must have at least 3 vertices, found {}", vertices.len()),
+                span: object.span,
+            })
+        }
+        let (pan_u, pan_v) = pan(object)?;
+        let link = header_int(object, "Link")?
+            .map(|link| i32::try_from(link).map_err(|_| T3dViewError {
+                message: String::from("Link is out of range"),
+                span: object.begin_span,
+            }))
+            .transpose()?;
+        Ok(T3dPolygon {
+            origin: origin.unwrap_or_default(),
+            normal: normal.unwrap_or_else(|| polygon_normal(&vertices)),
+            texture_u: texture_u.unwrap_or_default(),
+            texture_v: texture_v.unwrap_or_default(),
+            vertices,
+            pan_u,
+            pan_v,
+            texture: object.header_string("Texture"),
+            flags: PolyFlags(header_int(object, "Flags")?.unwrap_or(0) as u32),
+            flags_layout,
+            item: object.header_string("Item"),
+            link,
+        })
+    }
+
+    /// Writes the flags back to the `Flags` header of a `Begin Polygon` object, leaving it untouched if they are
+    /// unchanged. The header is removed when no flags are set, as UnrealEd does.
+    pub fn apply_to(&self, object: &mut T3dObject) {
+        if matches!(header_int(object, "Flags"), Ok(flags) if flags.unwrap_or(0) as u32 == self.flags.bits()) {
+            return
+        }
+        if self.flags == PolyFlags::default() {
+            object.remove_header_value("Flags");
+        } else {
+            object.set_header_value("Flags", T3dValue::Int(self.flags.bits() as i32 as i64));
+        }
+    }
+}
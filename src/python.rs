@@ -0,0 +1,1161 @@
+// The pyo3 0.20 macros generate impls for `#[new]` and `__setitem__` inside functions, which newer compilers warn
+// about.
+#![allow(non_local_definitions)]
+
+use std::any::Any;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Debug, Formatter};
+use pyo3::prelude::*;
+use pyo3::create_exception;
+use pyo3::exceptions::{PyIndexError, PyKeyError, PySyntaxError, PyTypeError, PyValueError};
+use pyo3::{PyTraverseError, PyVisit};
+use pyo3::types::{PyBool, PyDict, PyFloat, PyIterator, PyList, PyLong, PyString, PyTuple};
+use crate::ast::{T3dDiagnostic, T3dObject, T3dPropertyValue, T3dReference, T3dSpan, T3dStruct, T3dValue, T3dViewError};
+use crate::brush::{T3dBrush, T3dScale};
+use crate::csg;
+use crate::dialect::T3dDialect;
+use crate::flags::{PolyFlags, PolyFlagsLayout};
+use crate::polygon::{T3dPolygon, T3dVector};
+use crate::parser::{self, error_expected_rules, error_line_column, error_line_text, parse_t3d_recovering, parse_t3d_with_options, T3dParseOptions};
+use crate::encoding::{self, T3dEncoding};
+use crate::writer;
+use crate::export::{self, T3dExportError, T3dExportOptions};
+#[cfg(feature = "serde")]
+use crate::{gltf, json};
+
+#[pymethods]
+impl T3dSpan {
+    #[getter]
+    fn start(&self) -> usize {
+        self.start
+    }
+
+    #[getter]
+    fn end(&self) -> usize {
+        self.end
+    }
+
+    #[getter]
+    fn start_line(&self) -> usize {
+        self.start_line
+    }
+
+    #[getter]
+    fn start_column(&self) -> usize {
+        self.start_column
+    }
+
+    #[getter]
+    fn end_line(&self) -> usize {
+        self.end_line
+    }
+
+    #[getter]
+    fn end_column(&self) -> usize {
+        self.end_column
+    }
+
+    fn __repr__(&self) -> String {
+        format!("T3dSpan(start={}, end={}, line={}, column={})", self.start, self.end, self.start_line, self.start_column)
+    }
+
+    fn __str__(&self) -> String {
+        self.to_string()
+    }
+}
+
+#[pymethods]
+impl T3dReference {
+    #[new]
+    #[pyo3(signature = (type_, path, quoted = false))]
+    fn py_new(type_: &str, path: &str, quoted: bool) -> Self {
+        T3dReference { quoted, ..T3dReference::new(type_, path) }
+    }
+
+    #[getter(type_)]
+    fn get_type(&self) -> String {
+        self.type_.clone()
+    }
+
+    #[getter(path)]
+    fn get_path(&self) -> String {
+        self.path.clone()
+    }
+
+    #[getter(quoted)]
+    fn get_quoted(&self) -> bool {
+        self.quoted
+    }
+
+    #[getter(class_name)]
+    fn get_class_name(&self) -> &str {
+        self.class_name()
+    }
+
+    #[getter(package)]
+    fn get_package(&self) -> Option<&str> {
+        self.package()
+    }
+
+    #[getter(groups)]
+    fn get_groups(&self) -> Vec<&str> {
+        self.groups()
+    }
+
+    #[getter(object_name)]
+    fn get_object_name(&self) -> &str {
+        self.object_name()
+    }
+
+    #[getter(subobjects)]
+    fn get_subobjects(&self) -> Vec<&str> {
+        self.subobjects()
+    }
+
+    fn __repr__(&self) -> String {
+        self.to_string()
+    }
+
+    fn __str__(&self) -> String {
+        self.to_string()
+    }
+}
+
+#[pymethods]
+impl T3dPolygon {
+    /// Reads a polygon from a `Begin Polygon` object, raising `ValueError` if it is malformed. `flags_layout` is
+    /// `"ue1"` or `"ue2"`, unless a `dialect` such as `"UE2.5"` is given, which has its own layout.
+    #[staticmethod]
+    #[pyo3(name = "from_object", signature = (object, flags_layout = "ue1", dialect = None))]
+    fn py_from_object(object: &T3dObject, flags_layout: &str, dialect: Option<&str>) -> PyResult<Self> {
+        match dialect {
+            Some(dialect) => T3dPolygon::from_object_with_dialect(object, dialect_from_name(dialect)?),
+            None => T3dPolygon::from_object_with_layout(object, layout(flags_layout)?),
+        }.map_err(view_error)
+    }
+
+    #[getter(origin)]
+    fn get_origin(&self) -> T3dVector {
+        self.origin
+    }
+
+    #[getter(normal)]
+    fn get_normal(&self) -> T3dVector {
+        self.normal
+    }
+
+    #[getter(texture_u)]
+    fn get_texture_u(&self) -> T3dVector {
+        self.texture_u
+    }
+
+    #[getter(texture_v)]
+    fn get_texture_v(&self) -> T3dVector {
+        self.texture_v
+    }
+
+    #[getter(vertices)]
+    fn get_vertices(&self) -> Vec<T3dVector> {
+        self.vertices.clone()
+    }
+
+    #[getter(pan_u)]
+    fn get_pan_u(&self) -> i32 {
+        self.pan_u
+    }
+
+    #[getter(pan_v)]
+    fn get_pan_v(&self) -> i32 {
+        self.pan_v
+    }
+
+    #[getter(texture)]
+    fn get_texture(&self) -> Option<String> {
+        self.texture.clone()
+    }
+
+    /// The names of the set flags, e.g. `{"TwoSided", "Unlit"}`.
+    #[getter(flags)]
+    fn get_flags(&self) -> HashSet<String> {
+        self.flags.names(self.flags_layout).into_iter().collect()
+    }
+
+    /// Sets the flags from an integer or from an iterable of flag names.
+    #[setter(flags)]
+    fn set_flags(&mut self, value: &PyAny) -> PyResult<()> {
+        self.flags = flags_from_py(value, self.flags_layout)?;
+        Ok(())
+    }
+
+    /// Writes the flags back to the `Flags=` header of a `Begin Polygon` object, e.g. the one the polygon was read
+    /// from.
+    #[pyo3(name = "apply_to")]
+    fn py_apply_to(&self, object: &PyCell<T3dObject>) -> PyResult<()> {
+        self.apply_to(&mut *object.try_borrow_mut()?);
+        write_back(object)
+    }
+
+    /// The flags as the integer written to the `Flags=` header.
+    #[getter(flags_value)]
+    fn get_flags_value(&self) -> i32 {
+        self.flags.bits() as i32
+    }
+
+    #[getter(flags_layout)]
+    fn get_flags_layout(&self) -> &'static str {
+        self.flags_layout.name()
+    }
+
+    #[getter(item)]
+    fn get_item(&self) -> Option<String> {
+        self.item.clone()
+    }
+
+    #[getter(link)]
+    fn get_link(&self) -> Option<i32> {
+        self.link
+    }
+
+    fn __repr__(&self) -> String {
+        let texture = self.texture.as_ref().map_or(String::from("None"), |texture| format!("'{}'", texture));
+        format!("T3dPolygon(texture={}, vertices={})", texture, self.vertices.len())
+    }
+}
+
+#[pymethods]
+impl T3dScale {
+    #[getter(scale)]
+    fn get_scale(&self) -> T3dVector {
+        self.scale
+    }
+
+    #[getter(sheer_rate)]
+    fn get_sheer_rate(&self) -> f32 {
+        self.sheer_rate
+    }
+
+    /// The axis name, e.g. `"SHEER_ZX"`.
+    #[getter(sheer_axis)]
+    fn get_sheer_axis(&self) -> &'static str {
+        self.sheer_axis.name()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("T3dScale(scale={:?}, sheer_rate={}, sheer_axis='{}')", self.scale, self.sheer_rate, self.sheer_axis.name())
+    }
+}
+
+#[pymethods]
+impl T3dBrush {
+    /// Reads a brush from an actor with a `Begin Brush` model, raising `ValueError` if it is malformed.
+    /// `flags_layout` is `"ue1"` or `"ue2"`, unless a `dialect` such as `"UE4"` is given, which also reads UE4
+    /// component transforms.
+    #[staticmethod]
+    #[pyo3(name = "from_object", signature = (object, flags_layout = "ue1", dialect = None))]
+    fn py_from_object(object: &T3dObject, flags_layout: &str, dialect: Option<&str>) -> PyResult<Self> {
+        match dialect {
+            Some(dialect) => T3dBrush::from_object_with_dialect(object, dialect_from_name(dialect)?),
+            None => T3dBrush::from_object_with_layout(object, layout(flags_layout)?),
+        }.map_err(view_error)
+    }
+
+    #[getter(name)]
+    fn get_name(&self) -> Option<String> {
+        self.name.clone()
+    }
+
+    /// The operation name, e.g. `"CSG_Subtract"`.
+    #[getter(csg_oper)]
+    fn get_csg_oper(&self) -> &'static str {
+        self.csg_oper.name()
+    }
+
+    #[getter(location)]
+    fn get_location(&self) -> T3dVector {
+        self.location
+    }
+
+    #[getter(rotation)]
+    fn get_rotation(&self) -> T3dVector {
+        self.rotation
+    }
+
+    #[getter(pre_pivot)]
+    fn get_pre_pivot(&self) -> T3dVector {
+        self.pre_pivot
+    }
+
+    #[getter(main_scale)]
+    fn get_main_scale(&self) -> T3dScale {
+        self.main_scale
+    }
+
+    #[getter(post_scale)]
+    fn get_post_scale(&self) -> T3dScale {
+        self.post_scale
+    }
+
+    /// The names of the set `PolyFlags`, e.g. `{"Semisolid"}`.
+    #[getter(poly_flags)]
+    fn get_poly_flags(&self) -> HashSet<String> {
+        self.poly_flags.names(self.flags_layout).into_iter().collect()
+    }
+
+    /// Sets the `PolyFlags` from an integer or from an iterable of flag names.
+    #[setter(poly_flags)]
+    fn set_poly_flags(&mut self, value: &PyAny) -> PyResult<()> {
+        self.poly_flags = flags_from_py(value, self.flags_layout)?;
+        Ok(())
+    }
+
+    /// Writes the `PolyFlags` and the flags of the polygons back to the actor the brush was read from, raising
+    /// `ValueError` if it has no model or a different number of polygons.
+    #[pyo3(name = "apply_to")]
+    fn py_apply_to(&self, object: &PyCell<T3dObject>) -> PyResult<()> {
+        self.apply_to(&mut *object.try_borrow_mut()?).map_err(view_error)?;
+        refresh_children(object)?;
+        write_back(object)
+    }
+
+    /// The `PolyFlags` as the integer written to the property.
+    #[getter(poly_flags_value)]
+    fn get_poly_flags_value(&self) -> i32 {
+        self.poly_flags.bits() as i32
+    }
+
+    #[getter(flags_layout)]
+    fn get_flags_layout(&self) -> &'static str {
+        self.flags_layout.name()
+    }
+
+    #[getter(polygons)]
+    fn get_polygons(&self) -> Vec<T3dPolygon> {
+        self.polygons.clone()
+    }
+
+    /// Returns the polygons transformed into world space.
+    #[pyo3(name = "world_polygons")]
+    fn py_world_polygons(&self) -> Vec<T3dPolygon> {
+        self.world_polygons()
+    }
+
+    fn __repr__(&self) -> String {
+        let name = self.name.as_ref().map_or(String::from("None"), |name| format!("'{}'", name));
+        format!("T3dBrush(name={}, csg_oper='{}', polygons={})", name, self.csg_oper.name(), self.polygons.len())
+    }
+}
+
+fn dialect_from_name(name: &str) -> PyResult<T3dDialect> {
+    T3dDialect::from_name(name)
+        .ok_or_else(|| pyo3::exceptions::PyValueError::new_err(format!("unknown dialect: {}", name)))
+}
+
+fn layout(name: &str) -> PyResult<PolyFlagsLayout> {
+    PolyFlagsLayout::from_name(name)
+        .ok_or_else(|| pyo3::exceptions::PyValueError::new_err(format!("unknown flags layout: {}", name)))
+}
+
+/// Reads flags from an integer, which may be negative as in T3D files, or from a name or an iterable of names.
+fn flags_from_py(value: &PyAny, layout: PolyFlagsLayout) -> PyResult<PolyFlags> {
+    if let Ok(bits) = value.extract::<i64>() {
+        return u32::try_from(bits).or_else(|_| i32::try_from(bits).map(|bits| bits as u32))
+            .map(PolyFlags)
+            .map_err(|_| pyo3::exceptions::PyOverflowError::new_err("flags must fit in 32 bits"))
+    }
+    let names = match value.extract::<String>() {
+        Ok(name) => vec![name],
+        Err(_) => value.iter()?.map(|name| name.and_then(|name| name.extract::<String>())).collect::<PyResult<Vec<String>>>()?,
+    };
+    PolyFlags::from_names(names, layout).map_err(pyo3::exceptions::PyValueError::new_err)
+}
+
+fn view_error(error: T3dViewError) -> PyErr {
+    pyo3::exceptions::PyValueError::new_err(error.to_string())
+}
+
+#[pymethods]
+impl T3dDiagnostic {
+    #[getter(span)]
+    fn get_span(&self) -> T3dSpan {
+        self.span
+    }
+
+    #[getter(message)]
+    fn get_message(&self) -> String {
+        self.message.clone()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("T3dDiagnostic(span={}, message={:?})", self.span.__repr__(), self.message)
+    }
+
+    fn __str__(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl IntoPy<PyObject> for T3dValue {
+    fn into_py(self, py: Python<'_>) -> PyObject {
+        match self {
+            T3dValue::Int(value) => value.into_py(py),
+            T3dValue::Float(value) => value.into_py(py),
+            T3dValue::Bool(value) => value.into_py(py),
+            T3dValue::String(value) => value.into_py(py),
+            T3dValue::Struct(value) => value.as_ref().clone().into_py(py),
+            T3dValue::InlineStruct(value) => value.as_ref().clone().into_py(py),
+            T3dValue::Reference(value) => value.clone().into_py(py),
+            T3dValue::Identifier(value) => value.into_py(py),
+            T3dValue::Vector(value) => value.into_py(py),
+            T3dValue::Array(value) => value.into_py(py),
+        }
+    }
+}
+
+impl ToPyObject for T3dValue {
+    fn to_object(&self, py: Python<'_>) -> PyObject {
+        self.clone().into_py(py)
+    }
+}
+
+impl IntoPy<PyObject> for T3dPropertyValue {
+    fn into_py(self, py: Python<'_>) -> PyObject {
+        match self {
+            T3dPropertyValue::Value(value) => { value.into_py(py) }
+            T3dPropertyValue::Array(array) => { array.into_py(py) }
+        }
+    }
+}
+
+impl ToPyObject for T3dPropertyValue {
+    fn to_object(&self, py: Python<'_>) -> PyObject {
+        self.clone().into_py(py)
+    }
+}
+
+/// Converts a Python value to a T3D value, taking the kind of value from `like`, the value that it replaces, where
+/// Python cannot tell them apart: strings replace identifiers as identifiers, dictionaries replace inline structs
+/// as inline structs, and 3-tuples replace vectors as vectors. Otherwise, strings become strings, dictionaries
+/// become structs, and lists and tuples become arrays, with `None` for empty slots.
+fn value_from_py(value: &PyAny, like: Option<&T3dValue>) -> PyResult<T3dValue> {
+    if value.is_instance_of::<PyBool>() {
+        Ok(T3dValue::Bool(value.extract()?))
+    } else if value.is_instance_of::<PyLong>() {
+        Ok(T3dValue::Int(value.extract()?))
+    } else if value.is_instance_of::<PyFloat>() {
+        Ok(T3dValue::Float(value.extract()?))
+    } else if let Ok(text) = value.downcast::<PyString>() {
+        let text = String::from(text.to_str()?);
+        match like {
+            Some(T3dValue::Identifier(_)) => Ok(T3dValue::Identifier(text)),
+            _ => Ok(T3dValue::String(text)),
+        }
+    } else if let Ok(reference) = value.extract::<T3dReference>() {
+        Ok(T3dValue::Reference(reference))
+    } else if let Ok(dict) = value.downcast::<PyDict>() {
+        let members = match like {
+            Some(T3dValue::Struct(members) | T3dValue::InlineStruct(members)) => Some(members),
+            _ => None,
+        };
+        let mut properties = T3dStruct::new();
+        for (name, member) in dict {
+            let name: String = name.extract()?;
+            let member = value_from_py(member, members.and_then(|members| members.get(&name)))?;
+            properties.insert(name, member);
+        }
+        match like {
+            Some(T3dValue::InlineStruct(_)) => Ok(T3dValue::InlineStruct(Box::new(properties))),
+            _ => Ok(T3dValue::Struct(Box::new(properties))),
+        }
+    } else if let (Some(T3dValue::Vector(_)), Ok(vector)) = (like, value.extract::<(f32, f32, f32)>()) {
+        Ok(T3dValue::Vector(vector))
+    } else if value.is_instance_of::<PyList>() || value.is_instance_of::<PyTuple>() {
+        let elements = match like {
+            Some(T3dValue::Array(elements)) => elements.as_slice(),
+            _ => &[],
+        };
+        let values = value.iter()?.enumerate().map(|(i, element)| {
+            let element = element?;
+            if element.is_none() {
+                return Ok(None)
+            }
+            value_from_py(element, elements.get(i).and_then(Option::as_ref)).map(Some)
+        }).collect::<PyResult<Vec<Option<T3dValue>>>>()?;
+        Ok(T3dValue::Array(values))
+    } else {
+        Err(PyTypeError::new_err(format!("cannot convert {} to a T3D value", value.get_type().name()?)))
+    }
+}
+
+/// Reads a property key, which is either a name or a `(name, index)` tuple for an element of an array property,
+/// e.g. `("Skins", 0)` for `Skins(0)`.
+fn property_key(key: &PyAny) -> PyResult<(String, Option<i32>)> {
+    match key.extract::<(String, i32)>() {
+        Ok((name, index)) => Ok((name, Some(index))),
+        Err(_) => Ok((key.extract::<String>()?, None)),
+    }
+}
+
+/// Converts a dictionary of header attributes. Strings become identifiers, e.g. `Class=Brush`, unless they replace
+/// a string.
+fn header_from_py(object: &T3dObject, header: &PyDict) -> PyResult<Vec<(String, T3dValue)>> {
+    let identifier = T3dValue::Identifier(String::new());
+    header.iter().map(|(name, value)| {
+        let name: String = name.extract()?;
+        let like = object.header_value(name.as_str()).unwrap_or(&identifier);
+        Ok((name, value_from_py(value, Some(like))?))
+    }).collect()
+}
+
+/// The Python objects of an object's parent and children. The children of an object are only turned into Python
+/// objects when they are first accessed from Python, and the same ones are returned from then on, so that edits to
+/// them can be written back into the `children` of their ancestors.
+#[derive(Default)]
+struct Links {
+    parent: Option<Py<T3dObject>>,
+    children: Option<Vec<Py<T3dObject>>>,
+}
+
+/// The [`Links`] of an object. They are boxed as `Any` so that dropping an object does not refer to the Python API
+/// unless there are links, which keeps the `t3d` binary from having to link against Python.
+#[derive(Default)]
+pub(crate) struct PyLinks(Option<Box<dyn Any + Send>>);
+
+impl PyLinks {
+    fn get(&self) -> Option<&Links> {
+        self.0.as_ref().and_then(|links| links.downcast_ref::<Links>())
+    }
+
+    fn get_mut(&mut self) -> &mut Links {
+        if self.get().is_none() {
+            self.0 = Some(Box::new(Links::default()));
+        }
+        self.0.as_mut().and_then(|links| links.downcast_mut::<Links>()).expect("links were just set")
+    }
+
+    fn parent(&self) -> Option<&Py<T3dObject>> {
+        self.get().and_then(|links| links.parent.as_ref())
+    }
+
+    fn children(&self) -> Option<&Vec<Py<T3dObject>>> {
+        self.get().and_then(|links| links.children.as_ref())
+    }
+}
+
+/// A copy of an object is not linked to the Python objects of the original.
+impl Clone for PyLinks {
+    fn clone(&self) -> Self {
+        PyLinks::default()
+    }
+}
+
+impl Debug for PyLinks {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PyLinks").finish_non_exhaustive()
+    }
+}
+
+/// Returns the Python objects of the children of an object, creating them the first time.
+fn child_objects(cell: &PyCell<T3dObject>) -> PyResult<Vec<Py<T3dObject>>> {
+    let py = cell.py();
+    let parent: Py<T3dObject> = cell.into();
+    let mut object = cell.try_borrow_mut()?;
+    if object.py_links.children().is_none() {
+        let children = object.children.iter().map(|child| {
+            let mut child = child.clone();
+            child.py_links.get_mut().parent = Some(parent.clone_ref(py));
+            Py::new(py, child)
+        }).collect::<PyResult<Vec<Py<T3dObject>>>>()?;
+        object.py_links.get_mut().children = Some(children);
+    }
+    Ok(object.py_links.children().into_iter().flatten().map(|child| child.clone_ref(py)).collect())
+}
+
+/// Returns the parent of an object, if it is still one of the parent's children.
+fn parent_of(object: &Py<T3dObject>, py: Python<'_>) -> PyResult<Option<(Py<T3dObject>, usize)>> {
+    let Some(parent) = object.try_borrow(py)?.py_links.parent().map(|parent| parent.clone_ref(py)) else {
+        return Ok(None)
+    };
+    let index = parent.try_borrow(py)?.py_links.children().into_iter().flatten().position(|child| child.is(object));
+    Ok(index.map(|index| (parent, index)))
+}
+
+/// Returns the ancestors of an object, nearest first, along with the index of the object or ancestor below them.
+fn ancestors(object: &Py<T3dObject>, py: Python<'_>) -> PyResult<Vec<(Py<T3dObject>, usize)>> {
+    let mut ancestors: Vec<(Py<T3dObject>, usize)> = Vec::new();
+    let mut current = object.clone_ref(py);
+    while let Some((parent, index)) = parent_of(&current, py)? {
+        if parent.is(object) || ancestors.iter().any(|(ancestor, _)| ancestor.is(&parent)) {
+            break
+        }
+        ancestors.push((parent.clone_ref(py), index));
+        current = parent;
+    }
+    Ok(ancestors)
+}
+
+/// Writes an object that was edited from Python back into the `children` of its ancestors.
+fn write_back(cell: &PyCell<T3dObject>) -> PyResult<()> {
+    let py = cell.py();
+    let ancestors = ancestors(&cell.into(), py)?;
+    if ancestors.is_empty() {
+        return Ok(())
+    }
+    let object = cell.try_borrow()?.clone();
+    for (depth, (ancestor, _)) in ancestors.iter().enumerate() {
+        let mut ancestor = ancestor.try_borrow_mut(py)?;
+        let mut target = Some(&mut *ancestor);
+        for (_, index) in ancestors[..=depth].iter().rev() {
+            target = target.and_then(|target| target.children.get_mut(*index));
+        }
+        if let Some(target) = target {
+            *target = object.clone();
+        }
+    }
+    Ok(())
+}
+
+/// Updates the Python objects of the children of an object after its `children` were edited from Rust.
+fn refresh_children(cell: &PyCell<T3dObject>) -> PyResult<()> {
+    let py = cell.py();
+    let object = cell.try_borrow()?;
+    let children: Vec<(Py<T3dObject>, T3dObject)> = object.py_links.children().into_iter().flatten()
+        .zip(&object.children)
+        .map(|(py_child, child)| (py_child.clone_ref(py), child.clone()))
+        .collect();
+    drop(object);
+    for (py_child, child) in children {
+        let cell = py_child.as_ref(py);
+        let mut target = cell.try_borrow_mut()?;
+        let links = std::mem::take(&mut target.py_links);
+        *target = child;
+        target.py_links = links;
+        drop(target);
+        refresh_children(cell)?;
+    }
+    Ok(())
+}
+
+/// Makes `child` a child of `parent`, raising `ValueError` if that would make an object its own ancestor.
+fn adopt(parent: &PyCell<T3dObject>, child: &Py<T3dObject>) -> PyResult<()> {
+    let py = parent.py();
+    if child.is(parent) || ancestors(&parent.into(), py)?.iter().any(|(ancestor, _)| ancestor.is(child)) {
+        return Err(PyValueError::new_err("an object cannot be its own child"))
+    }
+    child.try_borrow_mut(py)?.py_links.get_mut().parent = Some(parent.into());
+    Ok(())
+}
+
+#[pymethods]
+impl T3dObject {
+    /// Creates an object, e.g. `T3dObject("Actor", header={"Class": "Brush", "Name": "Brush1"})`. Property values are
+    /// converted as by `object[name] = value`, and `children` are copied.
+    #[new]
+    #[pyo3(signature = (type_, header = None, properties = None, children = None))]
+    fn py_new(type_: &str, header: Option<&PyDict>, properties: Option<&PyDict>, children: Option<Vec<T3dObject>>) -> PyResult<Self> {
+        let mut object = T3dObject::new(type_);
+        if let Some(header) = header {
+            object.header = header_from_py(&object, header)?;
+        }
+        for (name, value) in properties.into_iter().flatten() {
+            object.set_item(name, value)?;
+        }
+        object.children = children.unwrap_or_default();
+        Ok(object)
+    }
+
+    #[getter(type_)]
+    fn get_type(&self) -> String {
+        self.type_.clone()
+    }
+
+    #[setter(type_)]
+    fn set_type(slf: &PyCell<Self>, type_: String) -> PyResult<()> {
+        slf.try_borrow_mut()?.type_ = type_;
+        write_back(slf)
+    }
+
+    /// The child objects. They are the objects themselves rather than copies, so they can be edited in place, e.g.
+    /// `actor.children[0]["Tag"] = "Door"`. Use `append_child` and `remove_child`, or assign a new list, to add or
+    /// remove children.
+    #[getter(children)]
+    fn get_children(slf: &PyCell<Self>) -> PyResult<Vec<Py<T3dObject>>> {
+        child_objects(slf)
+    }
+
+    #[setter(children)]
+    fn set_children(slf: &PyCell<Self>, children: Vec<Py<T3dObject>>) -> PyResult<()> {
+        let py = slf.py();
+        for child in &children {
+            adopt(slf, child)?;
+        }
+        let copies = children.iter().map(|child| Ok(child.try_borrow(py)?.clone())).collect::<PyResult<Vec<T3dObject>>>()?;
+        let mut object = slf.try_borrow_mut()?;
+        object.children = copies;
+        object.py_links.get_mut().children = Some(children);
+        drop(object);
+        write_back(slf)
+    }
+
+    fn append_child(slf: &PyCell<Self>, child: Py<T3dObject>) -> PyResult<()> {
+        let py = slf.py();
+        child_objects(slf)?;
+        adopt(slf, &child)?;
+        let copy = child.try_borrow(py)?.clone();
+        let mut object = slf.try_borrow_mut()?;
+        object.children.push(copy);
+        object.py_links.get_mut().children.get_or_insert_with(Vec::new).push(child);
+        drop(object);
+        write_back(slf)
+    }
+
+    /// Removes a child, given either as its index or as the child object (or one that is equal to it), and returns
+    /// it. Raises `IndexError` or `ValueError` if there is no such child.
+    #[pyo3(name = "remove_child")]
+    fn py_remove_child(slf: &PyCell<Self>, child: &PyAny) -> PyResult<Py<T3dObject>> {
+        let py = slf.py();
+        let children = child_objects(slf)?;
+        let index = match child.extract::<isize>() {
+            Ok(index) => {
+                let index = if index < 0 { index + children.len() as isize } else { index };
+                usize::try_from(index).ok().filter(|index| *index < children.len())
+                    .ok_or_else(|| PyIndexError::new_err("child index out of range"))?
+            },
+            Err(_) => {
+                let object = child.extract::<T3dObject>()?;
+                match children.iter().position(|c| c.is(child)) {
+                    Some(index) => index,
+                    None => slf.try_borrow()?.children.iter().position(|c| *c == object)
+                        .ok_or_else(|| PyValueError::new_err("object is not a child"))?,
+                }
+            },
+        };
+        let mut object = slf.try_borrow_mut()?;
+        object.remove_child(index);
+        let removed = object.py_links.get_mut().children.as_mut().map(|children| children.remove(index));
+        drop(object);
+        if let Some(removed) = &removed {
+            removed.try_borrow_mut(py)?.py_links.get_mut().parent = None;
+        }
+        write_back(slf)?;
+        Ok(removed.unwrap_or_else(|| children[index].clone_ref(py)))
+    }
+
+    #[getter(properties)]
+    fn get_properties(&self) -> HashMap<String, T3dPropertyValue> {
+        self.properties.clone()
+    }
+
+    #[getter(vector_properties)]
+    fn get_vector_properties(&self) -> Vec<(String, Vec<f32>)> {
+        self.vector_properties.clone()
+    }
+
+    #[setter(vector_properties)]
+    fn set_vector_properties(slf: &PyCell<Self>, vector_properties: Vec<(String, Vec<f32>)>) -> PyResult<()> {
+        let mut object = slf.try_borrow_mut()?;
+        object.vector_properties = vector_properties;
+        object.vector_property_spans.clear();
+        drop(object);
+        write_back(slf)
+    }
+
+    #[getter(custom_properties)]
+    fn get_custom_properties(&self) -> Vec<String> {
+        self.custom_properties.clone()
+    }
+
+    #[getter(span)]
+    fn get_span(&self) -> Option<T3dSpan> {
+        self.span
+    }
+
+    #[getter(begin_span)]
+    fn get_begin_span(&self) -> Option<T3dSpan> {
+        self.begin_span
+    }
+
+    #[getter(end_span)]
+    fn get_end_span(&self) -> Option<T3dSpan> {
+        self.end_span
+    }
+
+    #[getter]
+    fn header(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let header = PyDict::new(py);
+        for (name, value) in &self.header {
+            header.set_item(name, value.to_object(py))?;
+        }
+        Ok(header.to_object(py))
+    }
+
+    #[setter(header)]
+    fn set_header(slf: &PyCell<Self>, header: &PyDict) -> PyResult<()> {
+        let mut object = slf.try_borrow_mut()?;
+        object.header = header_from_py(&object, header)?;
+        drop(object);
+        write_back(slf)
+    }
+
+    #[pyo3(name = "property_span", signature = (name, index = None))]
+    fn py_property_span(&self, name: &str, index: Option<i32>) -> Option<T3dSpan> {
+        self.property_span(name, index)
+    }
+
+    #[getter(class_)]
+    fn get_class(&self) -> Option<String> {
+        self.class_name()
+    }
+
+    #[getter(name)]
+    fn get_name(&self) -> Option<String> {
+        self.name()
+    }
+
+    #[getter(archetype)]
+    fn get_archetype(&self) -> Option<T3dReference> {
+        self.archetype().cloned()
+    }
+
+    /// The `Begin Object` children, which can be edited in place like `children`.
+    #[getter(subobjects)]
+    fn get_subobjects(slf: &PyCell<Self>) -> PyResult<Vec<Py<T3dObject>>> {
+        let py = slf.py();
+        let mut subobjects = Vec::new();
+        for child in child_objects(slf)? {
+            if child.try_borrow(py)?.type_.eq_ignore_ascii_case("Object") {
+                subobjects.push(child);
+            }
+        }
+        Ok(subobjects)
+    }
+
+    #[pyo3(name = "subobject")]
+    fn py_subobject(slf: &PyCell<Self>, name: &str) -> PyResult<Option<Py<T3dObject>>> {
+        let index = {
+            let object = slf.try_borrow()?;
+            let subobject = object.subobject(name);
+            subobject.and_then(|subobject| object.children.iter().position(|child| std::ptr::eq(child, subobject)))
+        };
+        match index {
+            Some(index) => Ok(child_objects(slf)?.get(index).map(|child| child.clone_ref(slf.py()))),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the value of a property, or `default` if there is none. With `ignore_case=True`, the name is
+    /// matched without regard to case, as in UnrealScript.
+    #[pyo3(signature = (name, default = None, ignore_case = false))]
+    fn get(&self, py: Python<'_>, name: &str, default: Option<PyObject>, ignore_case: bool) -> PyObject {
+        let value = if ignore_case { self.property(name) } else { self.properties.get(name) };
+        match value {
+            Some(value) => value.to_object(py),
+            None => default.unwrap_or_else(|| py.None()),
+        }
+    }
+
+    /// Returns the name of a property as it is spelled in the object, matching `name` without regard to case, or
+    /// `None` if there is no such property.
+    #[pyo3(name = "property_key")]
+    fn py_property_key(&self, name: &str) -> Option<String> {
+        self.property_key(name).map(String::from)
+    }
+
+    /// Returns a property by name, or an element of an array property by `(name, index)`. Like the other item
+    /// methods, the name is matched without regard to case.
+    fn __getitem__(&self, py: Python<'_>, key: &PyAny) -> PyResult<PyObject> {
+        let (name, index) = property_key(key)?;
+        match (self.property(name.as_str()), index) {
+            (Some(value), None) => Ok(value.to_object(py)),
+            (Some(T3dPropertyValue::Array(values)), Some(index)) => values.iter()
+                .find(|(i, _)| *i == Some(index))
+                .map(|(_, value)| value.to_object(py))
+                .ok_or_else(|| PyKeyError::new_err("Property not found")),
+            _ => Err(PyKeyError::new_err("Property not found")),
+        }
+    }
+
+    /// Sets a property by name, or an element of an array property by `(name, index)`, such as `Skins(0)`.
+    fn __setitem__(slf: &PyCell<Self>, key: &PyAny, value: &PyAny) -> PyResult<()> {
+        slf.try_borrow_mut()?.set_item(key, value)?;
+        write_back(slf)
+    }
+
+    /// Deletes a property by name, or an element of an array property by `(name, index)`.
+    fn __delitem__(slf: &PyCell<Self>, key: &PyAny) -> PyResult<()> {
+        slf.try_borrow_mut()?.del_item(key)?;
+        write_back(slf)
+    }
+
+    fn __contains__(&self, key: &PyAny) -> PyResult<bool> {
+        let (name, index) = property_key(key)?;
+        Ok(match (self.property(name.as_str()), index) {
+            (Some(_), None) => true,
+            (Some(T3dPropertyValue::Array(values)), Some(index)) => values.iter().any(|(i, _)| *i == Some(index)),
+            _ => false,
+        })
+    }
+
+    fn __traverse__(&self, visit: PyVisit<'_>) -> Result<(), PyTraverseError> {
+        if let Some(parent) = self.py_links.parent() {
+            visit.call(parent)?;
+        }
+        for child in self.py_links.children().into_iter().flatten() {
+            visit.call(child)?;
+        }
+        Ok(())
+    }
+
+    fn __clear__(&mut self) {
+        self.py_links = PyLinks::default();
+    }
+
+    fn __len__(&self) -> usize {
+        self.properties.len()
+    }
+
+    /// Iterates over the property names in sorted order.
+    fn __iter__<'py>(&self, py: Python<'py>) -> PyResult<&'py PyIterator> {
+        PyIterator::from_object(PyList::new(py, self.keys()))
+    }
+
+    /// The property names in sorted order.
+    fn keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self.properties.keys().cloned().collect();
+        keys.sort();
+        keys
+    }
+
+    fn values(&self) -> Vec<T3dPropertyValue> {
+        self.keys().iter().map(|name| self.properties[name].clone()).collect()
+    }
+
+    /// The `(name, value)` pairs of the properties, sorted by name.
+    fn items(&self) -> Vec<(String, T3dPropertyValue)> {
+        self.keys().into_iter().map(|name| {
+            let value = self.properties[&name].clone();
+            (name, value)
+        }).collect()
+    }
+}
+
+impl T3dObject {
+    /// Returns the spelling of an existing property with the given name, so that it is replaced rather than
+    /// added a second time with different case.
+    fn resolve_key(&self, name: String) -> String {
+        self.property_key(name.as_str()).map(String::from).unwrap_or(name)
+    }
+
+    fn set_item(&mut self, key: &PyAny, value: &PyAny) -> PyResult<()> {
+        let (name, index) = property_key(key)?;
+        let name = self.resolve_key(name);
+        match (self.properties.get_mut(name.as_str()), index) {
+            (current, None) => {
+                let like = match current {
+                    Some(T3dPropertyValue::Value(current)) => Some(&*current),
+                    _ => None,
+                };
+                let value = value_from_py(value, like)?;
+                self.set_property(name.as_str(), T3dPropertyValue::Value(value));
+            },
+            (Some(T3dPropertyValue::Value(_)), Some(_)) => {
+                return Err(PyTypeError::new_err(format!("{} is not an array property", name)))
+            },
+            (Some(T3dPropertyValue::Array(values)), Some(index)) => {
+                match values.iter_mut().find(|(i, _)| *i == Some(index)) {
+                    Some((_, current)) => *current = value_from_py(value, Some(current))?,
+                    None => values.push((Some(index), value_from_py(value, None)?)),
+                }
+            },
+            (None, Some(index)) => {
+                let value = value_from_py(value, None)?;
+                self.set_property(name.as_str(), T3dPropertyValue::Array(vec![(Some(index), value)]));
+            },
+        }
+        Ok(())
+    }
+
+    fn del_item(&mut self, key: &PyAny) -> PyResult<()> {
+        let (name, index) = property_key(key)?;
+        let name = self.resolve_key(name);
+        match (self.properties.get_mut(name.as_str()), index) {
+            (Some(_), None) => {
+                self.remove_property(name.as_str());
+            },
+            (Some(T3dPropertyValue::Array(values)), Some(index)) if values.iter().any(|(i, _)| *i == Some(index)) => {
+                values.retain(|(i, _)| *i != Some(index));
+                if values.is_empty() {
+                    self.remove_property(name.as_str());
+                } else {
+                    self.property_spans.remove(name.as_str());
+                }
+            },
+            _ => return Err(PyKeyError::new_err("Property not found")),
+        }
+        Ok(())
+    }
+}
+
+create_exception!(t3dpy, T3dSyntaxError, PySyntaxError, "Raised when T3D text cannot be parsed.");
+
+/// Converts a parser error into a `T3dSyntaxError` with the standard `SyntaxError` location fields, plus an
+/// `expected` attribute listing the grammar rules that were expected at that location.
+fn syntax_error(py: Python<'_>, error: parser::T3dSyntaxError, contents: &str, filename: Option<&str>) -> PyErr {
+    let (line, column) = error_line_column(&error);
+    let message = error.variant.message().to_string();
+    let details = (filename.map(String::from), line, column, error_line_text(&error, contents).to_string());
+    let err = T3dSyntaxError::new_err((message, details));
+    if let Err(setattr_error) = err.value(py).setattr("expected", error_expected_rules(&error)) {
+        return setattr_error;
+    }
+    err
+}
+
+/// Parses T3D text into a list of objects.
+///
+/// With `recover=True`, malformed objects are skipped instead of raising a `T3dSyntaxError`, and a tuple of the
+/// objects and a list of `T3dDiagnostic` for everything that was skipped is returned instead.
+///
+/// `dialect` forces the text to be read as a dialect such as `"UE3"`, e.g. to decode string escapes even if the
+/// dialect cannot be detected.
+#[pyfunction]
+#[pyo3(signature = (contents, lossless = false, filename = None, recover = false, dialect = None))]
+fn read_t3d(py: Python<'_>, contents: &str, lossless: bool, filename: Option<&str>, recover: bool, dialect: Option<&str>) -> PyResult<PyObject> {
+    let options = T3dParseOptions { lossless, dialect: dialect.map(dialect_from_name).transpose()? };
+    if recover {
+        let (objects, diagnostics) = parse_t3d_recovering(contents, options);
+        return Ok((objects, diagnostics).into_py(py))
+    }
+    match parse_t3d_with_options(contents, options) {
+        Ok(objects) => {
+            Ok(objects.into_py(py))
+        },
+        Err(err) => {
+            Err(syntax_error(py, err, contents, filename))
+        }
+    }
+}
+
+/// Serializes objects to T3D text, for the editor of `dialect` if it is given.
+#[pyfunction]
+#[pyo3(signature = (objects, dialect = None))]
+fn write_t3d(objects: Vec<T3dObject>, dialect: Option<&str>) -> PyResult<String> {
+    match dialect {
+        Some(dialect) => Ok(writer::write_t3d_with_dialect(&objects, dialect_from_name(dialect)?)),
+        None => Ok(writer::write_t3d(&objects)),
+    }
+}
+
+/// Guesses the dialect of the objects from their headers, class names and polygons, e.g. `"UE2"`, or returns
+/// `None` if there is nothing to go by.
+#[pyfunction]
+fn detect_dialect(objects: Vec<T3dObject>) -> Option<&'static str> {
+    T3dDialect::detect(&objects).map(|dialect| dialect.name())
+}
+
+/// Reads a T3D file, detecting its encoding from its byte order mark.
+///
+/// Returns a tuple of what `read_t3d` returns and the name of the encoding, such as `"utf-16-le"`, which can be
+/// passed to `write_t3d_file` to write the file back in the same encoding.
+#[pyfunction]
+#[pyo3(signature = (path, lossless = false, recover = false, dialect = None))]
+fn read_t3d_file(py: Python<'_>, path: &str, lossless: bool, recover: bool, dialect: Option<&str>) -> PyResult<(PyObject, &'static str)> {
+    let (contents, encoding) = encoding::decode(std::fs::read(path)?.as_slice());
+    let objects = read_t3d(py, contents.as_str(), lossless, Some(path), recover, dialect)?;
+    Ok((objects, encoding.name()))
+}
+
+/// Writes objects to a T3D file in the given encoding, for the editor of `dialect` if it is given.
+#[pyfunction]
+#[pyo3(signature = (path, objects, encoding = "utf-8", dialect = None))]
+fn write_t3d_file(path: &str, objects: Vec<T3dObject>, encoding: &str, dialect: Option<&str>) -> PyResult<()> {
+    let encoding = encoding.parse::<T3dEncoding>().map_err(pyo3::exceptions::PyValueError::new_err)?;
+    let contents = write_t3d(objects, dialect)?;
+    Ok(std::fs::write(path, encoding::encode(contents.as_str(), encoding))?)
+}
+
+/// Exports the polygons of the objects to an OBJ file, along with a `.mtl` material library next to it.
+///
+/// Texture coordinates are scaled by `texture_sizes`, a dictionary of `(width, height)` by texture name, or by
+/// `texture_size` for textures that are not in it.
+///
+/// With `csg=True`, the level geometry built from the brushes is exported instead of the polygons of every brush.
+#[pyfunction]
+#[pyo3(signature = (objects, path, texture_size = (256, 256), texture_sizes = None, csg = false))]
+fn export_obj(
+    objects: Vec<T3dObject>,
+    path: &str,
+    texture_size: (u32, u32),
+    texture_sizes: Option<HashMap<String, (u32, u32)>>,
+    csg: bool,
+) -> PyResult<()> {
+    let options = T3dExportOptions { texture_size, texture_sizes: texture_sizes.unwrap_or_default(), csg };
+    export::export_obj_with_options(&objects, path, &options).map_err(export_error)
+}
+
+/// Exports the actors of the objects to a binary glTF (`.glb`) file, with a mesh for every brush.
+#[cfg(feature = "serde")]
+#[pyfunction]
+#[pyo3(signature = (objects, path, texture_size = (256, 256), texture_sizes = None))]
+fn export_glb(objects: Vec<T3dObject>, path: &str, texture_size: (u32, u32), texture_sizes: Option<HashMap<String, (u32, u32)>>) -> PyResult<()> {
+    let options = T3dExportOptions { texture_size, texture_sizes: texture_sizes.unwrap_or_default(), csg: false };
+    gltf::export_glb_with_options(&objects, path, &options).map_err(export_error)
+}
+
+fn export_error(error: T3dExportError) -> PyErr {
+    match error {
+        T3dExportError::Io(err) => err.into(),
+        T3dExportError::View(err) => view_error(err),
+    }
+}
+
+/// Replays the brushes of the objects in file order, returning the polygons of the level geometry in world space.
+#[pyfunction]
+fn csg_polygons(objects: Vec<T3dObject>) -> PyResult<Vec<T3dPolygon>> {
+    csg::csg_polygons(&objects).map_err(view_error)
+}
+
+/// Serializes objects to JSON, with every value tagged with its type.
+#[cfg(feature = "serde")]
+#[pyfunction]
+#[pyo3(signature = (objects, pretty = false))]
+fn to_json(objects: Vec<T3dObject>, pretty: bool) -> PyResult<String> {
+    let result = if pretty {
+        json::to_json_pretty(&objects)
+    } else {
+        json::to_json(&objects)
+    };
+    result.map_err(|err| pyo3::exceptions::PyValueError::new_err(err.to_string()))
+}
+
+/// Deserializes objects from JSON written by `to_json`.
+#[cfg(feature = "serde")]
+#[pyfunction]
+fn from_json(contents: &str) -> PyResult<Vec<T3dObject>> {
+    json::from_json(contents).map_err(|err| pyo3::exceptions::PyValueError::new_err(err.to_string()))
+}
+
+#[pymodule]
+fn t3dpy(py: Python<'_>, m: &PyModule) -> PyResult<()> {
+    m.add("T3dSyntaxError", py.get_type::<T3dSyntaxError>())?;
+    m.add_class::<T3dObject>()?;
+    m.add_class::<T3dReference>()?;
+    m.add_class::<T3dSpan>()?;
+    m.add_class::<T3dDiagnostic>()?;
+    m.add_class::<T3dPolygon>()?;
+    m.add_class::<T3dBrush>()?;
+    m.add_class::<T3dScale>()?;
+    m.add_function(wrap_pyfunction!(read_t3d, m)?)?;
+    m.add_function(wrap_pyfunction!(write_t3d, m)?)?;
+    m.add_function(wrap_pyfunction!(read_t3d_file, m)?)?;
+    m.add_function(wrap_pyfunction!(write_t3d_file, m)?)?;
+    m.add_function(wrap_pyfunction!(detect_dialect, m)?)?;
+    m.add_function(wrap_pyfunction!(export_obj, m)?)?;
+    m.add_function(wrap_pyfunction!(csg_polygons, m)?)?;
+    #[cfg(feature = "serde")]
+    m.add_function(wrap_pyfunction!(export_glb, m)?)?;
+    #[cfg(feature = "serde")]
+    m.add_function(wrap_pyfunction!(to_json, m)?)?;
+    #[cfg(feature = "serde")]
+    m.add_function(wrap_pyfunction!(from_json, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,303 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use crate::ast::*;
+use crate::dialect::T3dDialect;
+use crate::encoding::{encode, T3dEncoding};
+
+const INDENT: &str = "   ";
+
+struct T3dWriter {
+    output: String,
+    depth: usize,
+    /// Write strings verbatim instead of escaping them, for dialects that do not read escapes.
+    raw_strings: bool,
+}
+
+impl T3dWriter {
+    fn new(dialect: Option<T3dDialect>) -> Self {
+        T3dWriter {
+            output: String::new(),
+            depth: 0,
+            raw_strings: !dialect.is_some_and(|dialect| dialect.escapes_strings()),
+        }
+    }
+
+    fn line(&mut self, text: &str) {
+        for _ in 0..self.depth {
+            self.output.push_str(INDENT);
+        }
+        self.output.push_str(text);
+        self.output.push('\n');
+    }
+
+    fn object(&mut self, object: &T3dObject) {
+        match &object.layout {
+            Some(layout) => self.object_lossless(object, layout),
+            None => self.object_formatted(object),
+        }
+    }
+
+    fn object_formatted(&mut self, object: &T3dObject) {
+        self.line(format_begin(object, self.raw_strings).as_str());
+        self.depth += 1;
+        // Subobjects come first, as UnrealEd does, so that properties can refer to them.
+        let (subobjects, children): (Vec<&T3dObject>, Vec<&T3dObject>) = object.children.iter()
+            .partition(|child| child.type_.eq_ignore_ascii_case("Object"));
+        for subobject in subobjects {
+            self.child(subobject);
+        }
+        for (name, values) in &object.vector_properties {
+            self.line(format_vector_property(name, values).as_str());
+        }
+        let mut names: Vec<&String> = object.properties.keys().collect();
+        names.sort();
+        for name in names {
+            match &object.properties[name] {
+                T3dPropertyValue::Value(value) => {
+                    self.property(name, None, value)
+                }
+                T3dPropertyValue::Array(values) => {
+                    for (index, value) in values {
+                        self.property(name, *index, value)
+                    }
+                }
+            }
+        }
+        for value in &object.custom_properties {
+            self.line(format_custom_properties(value).as_str());
+        }
+        for child in children {
+            self.child(child);
+        }
+        self.depth -= 1;
+        self.line(format!("End {}", object.type_).as_str());
+    }
+
+    fn child(&mut self, child: &T3dObject) {
+        match &child.layout {
+            Some(layout) => {
+                // A lossless child of a formatted object is placed on its own line.
+                let mut layout = layout.clone();
+                layout.leading = INDENT.repeat(self.depth);
+                layout.trailing = String::from("\n");
+                self.object_lossless(child, &layout);
+            },
+            None => self.object_formatted(child)
+        }
+    }
+
+    fn property(&mut self, name: &str, index: Option<i32>, value: &T3dValue) {
+        self.line(format_property(name, index, value, self.raw_strings).as_str());
+    }
+
+    /// Writes an object that was parsed in lossless mode, re-using the original text of every statement that
+    /// has not been changed since. Statements that were removed are skipped, and new ones are appended at the end.
+    fn object_lossless(&mut self, object: &T3dObject, layout: &T3dLayout) {
+        self.output.push_str(layout.leading.as_str());
+        if object.header == layout.header {
+            self.output.push_str(layout.begin.as_str());
+        } else {
+            self.output.push_str(format_begin(object, self.raw_strings).as_str());
+        }
+        let mut written_properties: HashMap<(&str, Option<i32>), usize> = HashMap::new();
+        // Properties whose original text was written, so that their duplicate assignments can be too.
+        let mut unchanged_properties: HashSet<&str> = HashSet::new();
+        let mut written_children = vec![false; object.children.len()];
+        let mut vector_properties = object.vector_properties.iter().peekable();
+        let mut custom_properties = object.custom_properties.iter();
+        // The whitespace before the `End` keyword, however it is spelled.
+        let end_leading = &layout.end[..layout.end.len() - layout.end.trim_start().len()];
+        let mut last_leading = format!("{}{}", end_leading, INDENT);
+        for statement in &layout.statements {
+            match statement {
+                T3dLayoutStatement::Property { leading, raw, name, index, value } => {
+                    let count = written_properties.entry((name.as_str(), *index)).or_insert(0);
+                    let current = match object.properties.get(name) {
+                        Some(T3dPropertyValue::Value(current)) if index.is_none() && *count == 0 => Some(current),
+                        Some(T3dPropertyValue::Array(values)) => {
+                            values.iter().filter(|(i, _)| i == index).map(|(_, v)| v).nth(*count)
+                        },
+                        _ => None
+                    };
+                    *count += 1;
+                    if let Some(current) = current {
+                        self.output.push_str(leading.as_str());
+                        if current == value {
+                            self.output.push_str(raw.as_str());
+                            unchanged_properties.insert(name.as_str());
+                        } else {
+                            self.output.push_str(format_property(name, *index, current, self.raw_strings).as_str());
+                        }
+                        if leading.contains('\n') {
+                            last_leading.clone_from(leading);
+                        }
+                    }
+                },
+                T3dLayoutStatement::VectorProperty { leading, raw, name, value } => {
+                    if let Some((current_name, current)) = vector_properties.next_if(|(n, _)| n == name) {
+                        self.output.push_str(leading.as_str());
+                        if current == value {
+                            self.output.push_str(raw.as_str());
+                        } else {
+                            self.output.push_str(format_vector_property(current_name, current).as_str());
+                        }
+                        if leading.contains('\n') {
+                            last_leading.clone_from(leading);
+                        }
+                    }
+                },
+                T3dLayoutStatement::CustomProperties { leading, raw, value } => {
+                    if let Some(current) = custom_properties.next() {
+                        self.output.push_str(leading.as_str());
+                        if current == value {
+                            self.output.push_str(raw.as_str());
+                        } else {
+                            self.output.push_str(format_custom_properties(current).as_str());
+                        }
+                        if leading.contains('\n') {
+                            last_leading.clone_from(leading);
+                        }
+                    }
+                },
+                T3dLayoutStatement::Object(index) => {
+                    if let Some(child) = object.children.get(*index) {
+                        self.object(child);
+                        written_children[*index] = true;
+                    }
+                },
+                T3dLayoutStatement::Duplicate { leading, raw, name } => {
+                    if unchanged_properties.contains(name.as_str()) {
+                        self.output.push_str(leading.as_str());
+                        self.output.push_str(raw.as_str());
+                    }
+                }
+            }
+        }
+        // Anything that was added after parsing follows the indentation of the last statement.
+        let mut names: Vec<&String> = object.properties.keys().collect();
+        names.sort();
+        for name in names {
+            let lines: Vec<String> = match &object.properties[name] {
+                T3dPropertyValue::Value(value) => {
+                    if written_properties.contains_key(&(name.as_str(), None)) {
+                        vec![]
+                    } else {
+                        vec![format_property(name, None, value, self.raw_strings)]
+                    }
+                },
+                T3dPropertyValue::Array(values) => {
+                    let mut counts: HashMap<Option<i32>, usize> = HashMap::new();
+                    values.iter().filter_map(|(index, value)| {
+                        let count = counts.entry(*index).or_insert(0);
+                        *count += 1;
+                        let written = written_properties.get(&(name.as_str(), *index)).copied().unwrap_or(0);
+                        (*count > written).then(|| format_property(name, *index, value, self.raw_strings))
+                    }).collect()
+                }
+            };
+            for line in lines {
+                self.output.push_str(last_leading.as_str());
+                self.output.push_str(line.as_str());
+            }
+        }
+        for (name, values) in vector_properties {
+            self.output.push_str(last_leading.as_str());
+            self.output.push_str(format_vector_property(name, values).as_str());
+        }
+        for value in custom_properties {
+            self.output.push_str(last_leading.as_str());
+            self.output.push_str(format_custom_properties(value).as_str());
+        }
+        for (child, written) in object.children.iter().zip(written_children) {
+            if !written {
+                let mut writer = T3dWriter { output: String::new(), depth: 0, raw_strings: self.raw_strings };
+                writer.object(child);
+                for line in writer.output.lines() {
+                    self.output.push_str(last_leading.as_str());
+                    self.output.push_str(line);
+                }
+            }
+        }
+        self.output.push_str(layout.end.as_str());
+        self.output.push_str(layout.trailing.as_str());
+    }
+}
+
+/// Formats a value, without escaping strings if `raw_strings` is set.
+fn format_value(value: &T3dValue, raw_strings: bool) -> String {
+    if raw_strings {
+        format!("{:#}", value)
+    } else {
+        value.to_string()
+    }
+}
+
+/// Formats the `Begin` line of an object, including its header attributes.
+fn format_begin(object: &T3dObject, raw_strings: bool) -> String {
+    let mut begin = format!("Begin {}", object.type_);
+    for (name, value) in &object.header {
+        begin.push_str(format!(" {}={}", name, format_value(value, raw_strings)).as_str());
+    }
+    begin
+}
+
+/// Formats a property assignment such as `Skins(0)=Texture'Foo.Bar'` or `Pan U=0 V=0`.
+fn format_property(name: &str, index: Option<i32>, value: &T3dValue, raw_strings: bool) -> String {
+    match (value, index) {
+        (T3dValue::InlineStruct(_), None) => format!("{} {}", name, format_value(value, raw_strings)),
+        (_, None) => format!("{}={}", name, format_value(value, raw_strings)),
+        (_, Some(index)) => format!("{}({})={}", name, index, format_value(value, raw_strings)),
+    }
+}
+
+/// Formats a vector property such as `Origin   +00128.000000,+00016.000011,+00016.000000`.
+fn format_vector_property(name: &str, values: &[f32]) -> String {
+    let components: Vec<String> = values.iter()
+        .map(|value| format_vector_component(*value))
+        .collect();
+    format!("{:<8} {}", name, components.join(","))
+}
+
+/// Formats a `CustomProperties` statement such as `CustomProperties Pin (PinId=...,PinName="execute")`.
+fn format_custom_properties(value: &str) -> String {
+    format!("CustomProperties {}", value)
+}
+
+/// Serializes objects to T3D text that can be pasted or imported into UnrealEd. Strings are escaped if the objects
+/// are detected to be from UE3 or later, and written verbatim otherwise, matching how [`crate::parse_t3d`] reads
+/// them.
+pub fn write_t3d(objects: &[T3dObject]) -> String {
+    write_objects(objects, None)
+}
+
+/// Serializes objects to T3D text for the editor of a dialect, e.g. writing strings without escapes for UE1 and
+/// UE2, and leaving out the polygon headers and class packages that UE1 cannot read.
+pub fn write_t3d_with_dialect(objects: &[T3dObject], dialect: T3dDialect) -> String {
+    let mut objects = objects.to_vec();
+    for object in &mut objects {
+        dialect.adapt(object);
+    }
+    write_objects(&objects, Some(dialect))
+}
+
+/// Writes objects, detecting the dialect if none is given so that strings are written the way they were read.
+fn write_objects(objects: &[T3dObject], dialect: Option<T3dDialect>) -> String {
+    let mut writer = T3dWriter::new(dialect.or_else(|| T3dDialect::detect(objects)));
+    for object in objects {
+        if object.layout.is_none() && !writer.output.is_empty() && !writer.output.ends_with('\n') {
+            writer.output.push('\n');
+        }
+        writer.object(object);
+    }
+    writer.output
+}
+
+/// Serializes objects to T3D text in the given encoding.
+pub fn write_t3d_bytes(objects: &[T3dObject], encoding: T3dEncoding) -> Vec<u8> {
+    encode(write_t3d(objects).as_str(), encoding)
+}
+
+/// Writes objects to a T3D file in the given encoding, such as the one that the file was read in.
+pub fn write_t3d_file(path: impl AsRef<Path>, objects: &[T3dObject], encoding: T3dEncoding) -> std::io::Result<()> {
+    std::fs::write(path, write_t3d_bytes(objects, encoding))
+}
@@ -0,0 +1,102 @@
+"""Tests of the Python bindings. Run `maturin develop`, then `python -m unittest discover tests`."""
+import unittest
+
+from t3dpy import T3dBrush, T3dObject, T3dPolygon, read_t3d, write_t3d
+
+LEVEL = '''Begin Map
+Begin Actor Class=Light Name=Light0
+    Begin Object Class=LightComponent Name=LightComponent0
+        Brightness=1
+    End Object
+    Tag="A"
+End Actor
+End Map
+'''
+
+
+class ChildrenTest(unittest.TestCase):
+    def test_nested_edits_are_written(self):
+        objects = read_t3d(LEVEL)
+        level = objects[0]
+        self.assertIs(level.children[0], level.children[0])
+        level.children[0]['Tag'] = 'X'
+        level.children[0].children[0]['Brightness'] = 5
+        level.children[0].subobject('LightComponent0')['Radius'] = 2
+        text = write_t3d(objects)
+        self.assertIn('Tag="X"', text)
+        self.assertIn('Brightness=5', text)
+        self.assertIn('Radius=2', text)
+
+    def test_lossless_nested_edit(self):
+        objects = read_t3d(LEVEL, lossless=True)
+        objects[0].children[0]['Tag'] = 'X'
+        self.assertEqual(write_t3d(objects), LEVEL.replace('Tag="A"', 'Tag="X"'))
+
+    def test_appended_and_removed_children(self):
+        objects = read_t3d(LEVEL)
+        level = objects[0]
+        removed = level.remove_child(0)
+        removed['Tag'] = 'Removed'
+        light = T3dObject('Actor', header={'Class': 'Light', 'Name': 'Light1'})
+        level.append_child(light)
+        light['Tag'] = 'Appended'
+        text = write_t3d(objects)
+        self.assertNotIn('Removed', text)
+        self.assertIn('Tag="Appended"', text)
+        with self.assertRaises(ValueError):
+            light.append_child(level)
+
+
+class PropertiesTest(unittest.TestCase):
+    def test_names_ignore_case(self):
+        objects = read_t3d(BRUSH, lossless=True)
+        actor = objects[0].children[0]
+        actor['polyflags'] = 32
+        self.assertEqual(actor.keys(), ['PolyFlags'])
+        self.assertEqual(write_t3d(objects), BRUSH.replace('PolyFlags=8', 'PolyFlags=32'))
+        self.assertIn('POLYFLAGS', actor)
+        self.assertEqual(actor['polyFlags'], 32)
+        del actor['polyflags']
+        self.assertNotIn('PolyFlags', actor)
+        self.assertNotIn('PolyFlags', write_t3d(objects))
+
+
+BRUSH = '''Begin Map
+Begin Actor Class=Brush Name=Brush1
+    PolyFlags=8
+    Begin Brush Name=Model1
+        Begin PolyList
+            Begin Polygon Texture=Wall Flags=256
+                Vertex   +00000.000000,+00000.000000,+00000.000000
+                Vertex   +00128.000000,+00000.000000,+00000.000000
+                Vertex   +00000.000000,+00128.000000,+00000.000000
+            End Polygon
+        End PolyList
+    End Brush
+End Actor
+End Map
+'''
+
+
+class FlagsTest(unittest.TestCase):
+    def test_brush_flags_are_written(self):
+        objects = read_t3d(BRUSH, lossless=True)
+        actor = objects[0].children[0]
+        polygon_object = actor.children[0].children[0].children[0]
+        brush = T3dBrush.from_object(actor)
+        brush.poly_flags = {'Semisolid'}
+        brush.apply_to(actor)
+        self.assertEqual(write_t3d(objects), BRUSH.replace('PolyFlags=8', 'PolyFlags=32'))
+        self.assertEqual(polygon_object.header['Flags'], 256)
+
+        polygon = T3dPolygon.from_object(polygon_object)
+        polygon.flags = {'Invisible', 'NotSolid'}
+        polygon.apply_to(polygon_object)
+        text = write_t3d(objects)
+        self.assertIn('Begin Polygon Texture=Wall Flags=9', text)
+        self.assertEqual(T3dPolygon.from_object(read_t3d(text)[0].children[0].children[0].children[0].children[0]).flags,
+                         {'Invisible', 'NotSolid'})
+
+
+if __name__ == '__main__':
+    unittest.main()